@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// A minimal client for the SpacetimeDB HTTP API of a single server.
+pub struct ApiClient {
+    base_url: String,
+}
+
+/// The server's verdict on a token, from `GET /v1/identity/:identity/verify`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    Valid,
+    /// The token is accepted but belongs to a different identity
+    IdentityMismatch,
+    /// The token was rejected with the given HTTP status
+    Rejected(u16),
+}
+
+#[derive(Debug, Deserialize)]
+struct OwnedDatabases {
+    identities: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DatabaseNames {
+    names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebsocketToken {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewIdentity {
+    pub identity: String,
+    pub token: String,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Mints a fresh identity (`POST /v1/identity`) and returns it with its token.
+    pub fn create_identity(&self) -> Result<NewIdentity> {
+        let url = self.url("/v1/identity");
+        let response = ureq::post(&url)
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        response
+            .into_json()
+            .with_context(|| format!("Failed to parse the response from {}", url))
+    }
+
+    /// Asks the server whether `token` is valid and belongs to `identity`.
+    /// Errors mean the server could not be reached.
+    pub fn verify_token(&self, identity: &str, token: &str) -> Result<Verification> {
+        let url = self.url(&format!("/v1/identity/{}/verify", identity));
+        match ureq::get(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+        {
+            Ok(_) => Ok(Verification::Valid),
+            Err(ureq::Error::Status(400, _)) => Ok(Verification::IdentityMismatch),
+            Err(ureq::Error::Status(code, _)) => Ok(Verification::Rejected(code)),
+            Err(e) => Err(request_error(&url, e)),
+        }
+    }
+
+    /// Exchanges a long-lived token for a short-lived websocket token.
+    pub fn websocket_token(&self, token: &str) -> Result<String> {
+        let url = self.url("/v1/identity/websocket-token");
+        let response = ureq::post(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        let websocket_token: WebsocketToken = response
+            .into_json()
+            .with_context(|| format!("Failed to parse the response from {}", url))?;
+        Ok(websocket_token.token)
+    }
+
+    /// Lists the identities of the databases owned by `identity`.
+    pub fn owned_databases(&self, identity: &str, token: &str) -> Result<Vec<String>> {
+        let url = self.url(&format!("/v1/identity/{}/databases", identity));
+        let response = ureq::get(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        let databases: OwnedDatabases = response
+            .into_json()
+            .with_context(|| format!("Failed to parse the response from {}", url))?;
+        Ok(databases.identities)
+    }
+
+    /// Lists the names registered for a database.
+    pub fn database_names(&self, database_identity: &str) -> Result<Vec<String>> {
+        let url = self.url(&format!("/v1/database/{}/names", database_identity));
+        let response = ureq::get(&url).call().map_err(|e| request_error(&url, e))?;
+        let names: DatabaseNames = response
+            .into_json()
+            .with_context(|| format!("Failed to parse the response from {}", url))?;
+        Ok(names.names)
+    }
+}
+
+fn request_error(url: &str, error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            anyhow::anyhow!(
+                "Request to {} failed with HTTP {}: {}",
+                url,
+                code,
+                body.trim()
+            )
+        }
+        ureq::Error::Transport(transport) => {
+            anyhow::anyhow!("Could not reach the server: {}", transport)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_server::{unreachable_url, StubServer};
+
+    #[test]
+    fn create_identity_posts_and_parses_the_new_identity() {
+        let stub = StubServer::start(|_| {
+            (
+                200,
+                r#"{"identity": "c200abc", "token": "new.jwt.token"}"#.to_string(),
+            )
+        });
+        // A trailing slash on the server URL must not double up
+        let client = ApiClient::new(&format!("{}/", stub.url()));
+        let identity = client.create_identity().unwrap();
+        assert_eq!(identity.identity, "c200abc");
+        assert_eq!(identity.token, "new.jwt.token");
+
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/v1/identity");
+        assert_eq!(requests[0].authorization, None);
+    }
+
+    #[test]
+    fn create_identity_reports_http_errors_with_the_body() {
+        let stub = StubServer::start(|_| (503, "maintenance".to_string()));
+        let error = ApiClient::new(&stub.url())
+            .create_identity()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("HTTP 503"), "{}", error);
+        assert!(error.contains("maintenance"), "{}", error);
+        assert!(error.contains("/v1/identity"), "{}", error);
+    }
+
+    #[test]
+    fn create_identity_reports_unparsable_responses() {
+        let stub = StubServer::start(|_| (200, r#"{"identity": "c200abc"}"#.to_string()));
+        let error = format!(
+            "{:#}",
+            ApiClient::new(&stub.url()).create_identity().unwrap_err()
+        );
+        assert!(error.contains("Failed to parse the response"), "{}", error);
+    }
+
+    #[test]
+    fn create_identity_reports_unreachable_servers() {
+        let error = ApiClient::new(&unreachable_url())
+            .create_identity()
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Could not reach the server"), "{}", error);
+    }
+
+    #[test]
+    fn verify_token_maps_statuses_to_verdicts() {
+        let stub = StubServer::start(|request| {
+            let status = match request.authorization.as_deref() {
+                Some("Bearer valid") => 204,
+                Some("Bearer mismatched") => 400,
+                _ => 401,
+            };
+            (status, String::new())
+        });
+        let client = ApiClient::new(&stub.url());
+        assert_eq!(
+            client.verify_token("c200abc", "valid").unwrap(),
+            Verification::Valid
+        );
+        assert_eq!(
+            client.verify_token("c200abc", "mismatched").unwrap(),
+            Verification::IdentityMismatch
+        );
+        assert_eq!(
+            client.verify_token("c200abc", "expired").unwrap(),
+            Verification::Rejected(401)
+        );
+
+        let requests = stub.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/v1/identity/c200abc/verify");
+    }
+
+    #[test]
+    fn verify_token_fails_when_the_server_is_unreachable() {
+        let error = ApiClient::new(&unreachable_url())
+            .verify_token("c200abc", "valid")
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Could not reach the server"), "{}", error);
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+
+const BACKUP_FORMAT: &str = "stt-backup-v1";
+const SALT_LEN: usize = 16;
+
+/// On-disk layout of an encrypted backup. The key is derived from a
+/// passphrase with Argon2id and the payload is sealed with ChaCha20-Poly1305.
+#[derive(Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive backup key: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypts `plaintext` and returns the contents of a backup file.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt backup."))?;
+
+    let file = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    toml::to_string_pretty(&file).context("Failed to serialize backup file")
+}
+
+/// Decrypts the contents of a backup file written by [`encrypt`].
+pub fn decrypt(content: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let file: BackupFile = toml::from_str(content).context("Not a valid backup file.")?;
+    if file.format != BACKUP_FORMAT {
+        anyhow::bail!("Unsupported backup format '{}'.", file.format);
+    }
+    let salt = STANDARD
+        .decode(&file.salt)
+        .context("Invalid salt in backup.")?;
+    let nonce = STANDARD
+        .decode(&file.nonce)
+        .context("Invalid nonce in backup.")?;
+    if nonce.len() != 12 {
+        anyhow::bail!("Invalid nonce length in backup.");
+    }
+    let ciphertext = STANDARD
+        .decode(&file.ciphertext)
+        .context("Invalid ciphertext in backup.")?;
+    let key = derive_key(passphrase, &salt)?;
+    ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted backup."))
+}
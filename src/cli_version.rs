@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use std::{fmt, path::Path, process::Command};
+
+/// Token key used by SpacetimeDB CLI 0.12 and later
+pub const TOKEN_KEY: &str = "spacetimedb_token";
+/// Token key of the `identity_configs` layout used before 0.12
+pub const LEGACY_TOKEN_KEY: &str = "identity_configs[identity=$default_identity].token";
+
+/// The version of an installed SpacetimeDB CLI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CliVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl CliVersion {
+    /// Runs `<binary> --version` and parses its output.
+    pub fn detect(binary: &Path) -> Result<Self> {
+        let output = Command::new(binary)
+            .arg("--version")
+            .output()
+            .with_context(|| format!("Failed to execute command: {}", binary.display()))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Self::parse(&stdout).with_context(|| {
+            format!(
+                "Could not find a version in the output of '{} --version'.",
+                binary.display()
+            )
+        })
+    }
+
+    /// Finds the version in `spacetime --version` output, preferring the
+    /// "tool version" line over other version-like text such as paths.
+    pub fn parse(output: &str) -> Option<Self> {
+        let preferred = output
+            .split_once("tool version")
+            .map(|(_, rest)| rest)
+            .into_iter();
+        preferred.chain(std::iter::once(output)).find_map(|text| {
+            text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .find_map(parse_triple)
+        })
+    }
+
+    /// Whether this CLI keeps the token in the pre-0.12 `identity_configs` layout.
+    pub fn uses_legacy_layout(&self) -> bool {
+        (self.major, self.minor) < (0, 12)
+    }
+
+    /// The token key this CLI writes to cli.toml.
+    pub fn expected_token_key(&self) -> &'static str {
+        if self.uses_legacy_layout() {
+            LEGACY_TOKEN_KEY
+        } else {
+            TOKEN_KEY
+        }
+    }
+
+    /// Explains why `token_key` does not fit this CLI's cli.toml layout, if it doesn't.
+    pub fn layout_warning(&self, token_key: &str) -> Option<String> {
+        let legacy_key = token_key.starts_with("identity_configs");
+        if legacy_key == self.uses_legacy_layout() {
+            return None;
+        }
+        Some(format!(
+            "SpacetimeDB CLI {} stores its token under '{}', but cli_token_key is '{}'.",
+            self,
+            self.expected_token_key(),
+            token_key
+        ))
+    }
+}
+
+fn parse_triple(text: &str) -> Option<CliVersion> {
+    let mut parts = text.trim_matches('.').split('.');
+    let version = CliVersion {
+        major: parts.next()?.parse().ok()?,
+        minor: parts.next()?.parse().ok()?,
+        patch: parts.next()?.parse().ok()?,
+    };
+    Some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u64, minor: u64, patch: u64) -> CliVersion {
+        CliVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn parse_prefers_the_tool_version_line() {
+        let output = "spacetime Path: /home/me/.local/share/spacetime/bin/1.2.0/spacetimedb-cli\n\
+                      Commit: 4f2a9c1\n\
+                      spacetimedb tool version 1.1.2; spacetimedb-lib version 1.1.2;\n";
+        assert_eq!(CliVersion::parse(output), Some(version(1, 1, 2)));
+        assert_eq!(
+            CliVersion::parse("spacetime 0.11.3\n"),
+            Some(version(0, 11, 3))
+        );
+        assert_eq!(
+            CliVersion::parse("version v0.12.0."),
+            Some(version(0, 12, 0))
+        );
+        assert_eq!(CliVersion::parse("spacetime 1.2\n"), None);
+        assert_eq!(CliVersion::parse(""), None);
+    }
+
+    #[test]
+    fn legacy_layout_ends_at_0_12() {
+        assert!(version(0, 11, 99).uses_legacy_layout());
+        assert!(!version(0, 12, 0).uses_legacy_layout());
+        assert!(!version(1, 0, 0).uses_legacy_layout());
+        assert_eq!(version(0, 11, 0).expected_token_key(), LEGACY_TOKEN_KEY);
+        assert_eq!(version(1, 1, 2).expected_token_key(), TOKEN_KEY);
+    }
+
+    #[test]
+    fn layout_warning_only_for_the_other_layout() {
+        assert_eq!(version(1, 1, 2).layout_warning(TOKEN_KEY), None);
+        assert_eq!(version(0, 11, 0).layout_warning(LEGACY_TOKEN_KEY), None);
+        let warning = version(1, 1, 2).layout_warning(LEGACY_TOKEN_KEY).unwrap();
+        assert!(warning.contains("'spacetimedb_token'"), "{}", warning);
+        assert!(version(0, 11, 0).layout_warning(TOKEN_KEY).is_some());
+    }
+}
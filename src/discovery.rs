@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A place the SpacetimeDB CLI may keep its config file
+pub struct Candidate {
+    pub path: PathBuf,
+    pub description: &'static str,
+}
+
+impl Candidate {
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// Whether the file uses the pre-0.12 `identity_configs` layout.
+    pub fn is_legacy(&self) -> bool {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| content.parse::<toml_edit::DocumentMut>().ok())
+            .is_some_and(|doc| doc.contains_key("identity_configs"))
+    }
+}
+
+/// Expands a configured directory: `~` and `~/...` become the home
+/// directory, `$VAR` and `${VAR}` are replaced by environment variables,
+/// and anything still relative is taken relative to the home directory.
+pub fn expand_dir(raw: &str) -> Result<PathBuf> {
+    expand_dir_in(raw, dirs::home_dir(), |name| std::env::var(name).ok())
+}
+
+/// [`expand_dir`] with the home directory and variables passed in.
+fn expand_dir_in(
+    raw: &str,
+    home_dir: Option<PathBuf>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf> {
+    let home_dir = || home_dir.clone().context("Failed to get home directory");
+    let expanded = if raw == "~" {
+        home_dir()?.to_string_lossy().into_owned()
+    } else if let Some(rest) = raw.strip_prefix("~/") {
+        home_dir()?.join(rest).to_string_lossy().into_owned()
+    } else {
+        expand_vars(raw, var)?
+    };
+    let path = PathBuf::from(expanded);
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(home_dir()?.join(path))
+    }
+}
+
+fn expand_vars(raw: &str, var: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut output = String::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced
+                    .find('}')
+                    .with_context(|| format!("Unclosed '${{' in '{}'", raw))?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            output.push('$');
+        } else {
+            let value = var(name).with_context(|| {
+                format!(
+                    "Environment variable '{}' used in '{}' is not set",
+                    name, raw
+                )
+            })?;
+            output.push_str(&value);
+        }
+        rest = remainder;
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Every location the SpacetimeDB CLI is known to use, most current first.
+/// `filename` is the configured cli.toml name.
+pub fn cli_config_candidates(filename: &str) -> Vec<Candidate> {
+    candidates_in(
+        filename,
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+        dirs::home_dir(),
+        dirs::config_dir(),
+    )
+}
+
+/// [`cli_config_candidates`] with the directories passed in.
+fn candidates_in(
+    filename: &str,
+    xdg_config_home: Option<PathBuf>,
+    home_dir: Option<PathBuf>,
+    config_dir: Option<PathBuf>,
+) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    if let Some(xdg) = xdg_config_home.filter(|dir| !dir.as_os_str().is_empty()) {
+        candidates.push(Candidate {
+            path: xdg.join("spacetime").join(filename),
+            description: "$XDG_CONFIG_HOME/spacetime",
+        });
+    }
+    if let Some(home_dir) = &home_dir {
+        candidates.push(Candidate {
+            path: home_dir.join(".config").join("spacetime").join(filename),
+            description: "~/.config/spacetime",
+        });
+    }
+    if let Some(config_dir) = config_dir {
+        candidates.push(Candidate {
+            path: config_dir.join("spacetime").join(filename),
+            description: "platform config directory",
+        });
+    }
+    if let Some(home_dir) = home_dir {
+        let legacy_dir = home_dir.join(".spacetime");
+        candidates.push(Candidate {
+            path: legacy_dir.join(filename),
+            description: "legacy ~/.spacetime",
+        });
+        candidates.push(Candidate {
+            path: legacy_dir.join("config.toml"),
+            description: "legacy ~/.spacetime (before 0.12)",
+        });
+    }
+
+    let mut seen = Vec::new();
+    candidates.retain(|candidate| {
+        let duplicate = seen.contains(&candidate.path);
+        seen.push(candidate.path.clone());
+        !duplicate
+    });
+    candidates
+}
+
+/// How a detected directory is written back to `cli_config_dir_from_home`:
+/// relative to the home directory where possible, absolute otherwise.
+pub fn dir_setting(dir: &Path) -> String {
+    match dirs::home_dir()
+        .and_then(|home_dir| dir.strip_prefix(home_dir).ok().map(Path::to_path_buf))
+    {
+        Some(relative) if !relative.as_os_str().is_empty() => {
+            relative.to_string_lossy().into_owned()
+        }
+        _ => dir.to_string_lossy().into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(raw: &str) -> Result<PathBuf> {
+        expand_dir_in(raw, Some(PathBuf::from("/home/me")), |name| {
+            (name == "DATA").then(|| "/srv/data".to_string())
+        })
+    }
+
+    #[test]
+    fn expand_dir_handles_home_variables_and_relative_paths() {
+        for (raw, expected) in [
+            ("~", "/home/me"),
+            ("~/.config/spacetime", "/home/me/.config/spacetime"),
+            (".config/spacetime", "/home/me/.config/spacetime"),
+            ("/etc/spacetime", "/etc/spacetime"),
+            ("$DATA/spacetime", "/srv/data/spacetime"),
+            ("${DATA}x", "/srv/datax"),
+            ("cost$", "/home/me/cost$"),
+        ] {
+            assert_eq!(expand(raw).unwrap(), PathBuf::from(expected), "{}", raw);
+        }
+        for (raw, message) in [
+            ("$MISSING/x", "'MISSING' used in '$MISSING/x' is not set"),
+            ("${DATA", "Unclosed '${'"),
+        ] {
+            let error = expand(raw).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", raw, error);
+        }
+        assert!(expand_dir_in("~/x", None, |_| None).is_err());
+    }
+
+    #[test]
+    fn candidates_are_ordered_most_current_first_without_duplicates() {
+        let paths = |candidates: Vec<Candidate>| -> Vec<PathBuf> {
+            candidates
+                .into_iter()
+                .map(|candidate| candidate.path)
+                .collect()
+        };
+        let home = || Some(PathBuf::from("/home/me"));
+        assert_eq!(
+            paths(candidates_in(
+                "cli.toml",
+                Some(PathBuf::from("/xdg")),
+                home(),
+                Some(PathBuf::from("/home/me/Library/Application Support")),
+            )),
+            [
+                "/xdg/spacetime/cli.toml",
+                "/home/me/.config/spacetime/cli.toml",
+                "/home/me/Library/Application Support/spacetime/cli.toml",
+                "/home/me/.spacetime/cli.toml",
+                "/home/me/.spacetime/config.toml",
+            ]
+            .map(PathBuf::from)
+        );
+        // On Linux the platform config directory is ~/.config, listed once;
+        // an empty XDG_CONFIG_HOME counts as unset
+        assert_eq!(
+            paths(candidates_in(
+                "cli.toml",
+                Some(PathBuf::new()),
+                home(),
+                Some(PathBuf::from("/home/me/.config")),
+            )),
+            [
+                "/home/me/.config/spacetime/cli.toml",
+                "/home/me/.spacetime/cli.toml",
+                "/home/me/.spacetime/config.toml",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
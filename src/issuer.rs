@@ -0,0 +1,55 @@
+use crate::keys::IssuerKey;
+use anyhow::Result;
+use tiny_http::{Header, Response, Server};
+
+const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// The OpenID discovery document for an issuer that only publishes its keys.
+pub fn openid_configuration(issuer_url: &str) -> serde_json::Value {
+    serde_json::json!({
+        "issuer": issuer_url,
+        "jwks_uri": format!("{}{}", issuer_url, JWKS_PATH),
+        "id_token_signing_alg_values_supported": ["ES256"],
+        "response_types_supported": ["id_token"],
+        "subject_types_supported": ["public"],
+    })
+}
+
+pub fn jwks(key: &IssuerKey) -> serde_json::Value {
+    serde_json::json!({ "keys": [key.public_jwk()] })
+}
+
+/// Serves the discovery document and JWKS on `address` until the process is stopped.
+pub fn serve(address: &str, issuer_url: &str, key: &IssuerKey) -> Result<()> {
+    let server = Server::http(address)
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+    let configuration = openid_configuration(issuer_url).to_string();
+    let jwks = jwks(key).to_string();
+    let content_type: Header = "Content-Type: application/json"
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid Content-Type header"))?;
+
+    for request in server.incoming_requests() {
+        let path = request.url().split('?').next().unwrap_or_default();
+        let body = match path {
+            "/.well-known/openid-configuration" => Some(&configuration),
+            JWKS_PATH => Some(&jwks),
+            _ => None,
+        };
+        println!(
+            "{} {} -> {}",
+            request.method(),
+            path,
+            if body.is_some() { 200 } else { 404 }
+        );
+        let result = match body {
+            Some(body) => request
+                .respond(Response::from_string(body.as_str()).with_header(content_type.clone())),
+            None => request.respond(Response::from_string("Not Found").with_status_code(404)),
+        };
+        if let Err(e) = result {
+            println!("Warning: Failed to send response: {}", e);
+        }
+    }
+    Ok(())
+}
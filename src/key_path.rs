@@ -0,0 +1,517 @@
+use anyhow::Result;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, TableLike};
+
+/// A path to a value inside a TOML document, such as `spacetimedb_token`,
+/// `auth.token` or `identity_configs[identity=$default_identity].token`.
+///
+/// Segments are separated by `.`. A segment may select one table of an
+/// array of tables, either by index (`name[0]`) or by the value of one of
+/// its keys (`name[key=value]`). A value starting with `$` refers to the
+/// string at that (plain, dotted) path from the document root, which is how
+/// the legacy `default_identity`/`identity_configs` layout is addressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    key: String,
+    selector: Option<Selector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Index(usize),
+    Match { key: String, value: MatchValue },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MatchValue {
+    Literal(String),
+    Reference(KeyPath),
+}
+
+impl KeyPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        for raw in split_segments(path)? {
+            let (key, selector) = match raw.split_once('[') {
+                Some((key, rest)) => {
+                    let inner = rest.strip_suffix(']').ok_or_else(|| {
+                        anyhow::anyhow!("Unclosed '[' in key path segment '{}'.", raw)
+                    })?;
+                    (key, Some(parse_selector(inner)?))
+                }
+                None => (raw, None),
+            };
+            if key.is_empty() {
+                anyhow::bail!("Empty segment in key path '{}'.", path);
+            }
+            segments.push(Segment {
+                key: key.to_string(),
+                selector,
+            });
+        }
+        if segments.is_empty() {
+            anyhow::bail!("Key path is empty.");
+        }
+        if segments
+            .last()
+            .is_some_and(|segment| segment.selector.is_some())
+        {
+            anyhow::bail!(
+                "Key path '{}' must end in a key, not an array selector.",
+                path
+            );
+        }
+        Ok(Self { segments })
+    }
+
+    /// The keys of a path without array selectors, for formats that have no
+    /// arrays of tables.
+    pub fn plain_keys(&self) -> Result<Vec<&str>> {
+        self.segments
+            .iter()
+            .map(|segment| match segment.selector {
+                None => Ok(segment.key.as_str()),
+                Some(_) => anyhow::bail!(
+                    "Key path '{}' uses an array selector, which only TOML files support.",
+                    self
+                ),
+            })
+            .collect()
+    }
+
+    /// Looks up the item at this path.
+    pub fn get<'a>(&self, doc: &'a DocumentMut) -> Option<&'a Item> {
+        let (last, parents) = self.segments.split_last()?;
+        let mut table: &dyn TableLike = doc.as_table();
+        for segment in parents {
+            let item = table.get(&segment.key)?;
+            table = match &segment.selector {
+                None => item.as_table_like()?,
+                Some(selector) => {
+                    let tables = item.as_array_of_tables()?;
+                    let index = find_index(doc, tables, selector)?;
+                    tables.get(index)?
+                }
+            };
+        }
+        table.get(&last.key)
+    }
+
+    /// Sets the string at this path, creating intermediate tables (and
+    /// entries of arrays of tables) as needed.
+    pub fn set(&self, doc: &mut DocumentMut, value: &str) -> Result<()> {
+        let selectors = self.resolve_selectors(doc)?;
+        let (last, parents) = self
+            .segments
+            .split_last()
+            .ok_or_else(|| anyhow::anyhow!("Key path is empty."))?;
+        let mut table: &mut dyn TableLike = doc.as_table_mut();
+        for (segment, selector) in parents.iter().zip(&selectors) {
+            match selector {
+                None => {
+                    let item = table
+                        .entry(&segment.key)
+                        .or_insert_with(|| Item::Table(Table::new()));
+                    table = item
+                        .as_table_like_mut()
+                        .ok_or_else(|| anyhow::anyhow!("'{}' is not a table.", segment.key))?;
+                }
+                Some(resolved) => {
+                    let item = table
+                        .entry(&segment.key)
+                        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()));
+                    let tables = item.as_array_of_tables_mut().ok_or_else(|| {
+                        anyhow::anyhow!("'{}' is not an array of tables.", segment.key)
+                    })?;
+                    let index = match resolved {
+                        ResolvedSelector::Index(index) if *index <= tables.len() => *index,
+                        ResolvedSelector::Index(index) => anyhow::bail!(
+                            "Index {} is out of bounds for '{}' ({} entries).",
+                            index,
+                            segment.key,
+                            tables.len()
+                        ),
+                        ResolvedSelector::Match { key, value } => tables
+                            .iter()
+                            .position(|t| t.get(key).and_then(|v| v.as_str()) == Some(value))
+                            .unwrap_or(tables.len()),
+                    };
+                    if index == tables.len() {
+                        let mut new_table = Table::new();
+                        if let ResolvedSelector::Match { key, value } = resolved {
+                            new_table[key.as_str()] = toml_edit::value(value.clone());
+                        }
+                        tables.push(new_table);
+                    }
+                    table = tables
+                        .get_mut(index)
+                        .ok_or_else(|| anyhow::anyhow!("Failed to select '{}'.", segment.key))?;
+                }
+            }
+        }
+        match table.get_mut(&last.key) {
+            // Swap only the value, so comments around the key and after it survive
+            Some(Item::Value(existing)) => {
+                let decor = existing.decor().clone();
+                *existing = toml_edit::Value::from(value);
+                *existing.decor_mut() = decor;
+            }
+            _ => {
+                table.insert(&last.key, toml_edit::value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the item at this path. Returns whether anything was removed.
+    pub fn remove(&self, doc: &mut DocumentMut) -> bool {
+        let Ok(selectors) = self.resolve_selectors(doc) else {
+            return false;
+        };
+        let Some((last, parents)) = self.segments.split_last() else {
+            return false;
+        };
+        let mut table: &mut dyn TableLike = doc.as_table_mut();
+        for (segment, selector) in parents.iter().zip(&selectors) {
+            let Some(item) = table.get_mut(&segment.key) else {
+                return false;
+            };
+            let next = match selector {
+                None => item.as_table_like_mut(),
+                Some(resolved) => item.as_array_of_tables_mut().and_then(|tables| {
+                    let index = match resolved {
+                        ResolvedSelector::Index(index) => Some(*index),
+                        ResolvedSelector::Match { key, value } => tables
+                            .iter()
+                            .position(|t| t.get(key).and_then(|v| v.as_str()) == Some(value)),
+                    }?;
+                    tables
+                        .get_mut(index)
+                        .map(|table| table as &mut dyn TableLike)
+                }),
+            };
+            let Some(next) = next else {
+                return false;
+            };
+            table = next;
+        }
+        table.remove(&last.key).is_some()
+    }
+
+    /// Resolves `$references` in selectors up front, since they have to be
+    /// read from the document before it is borrowed mutably.
+    fn resolve_selectors(&self, doc: &DocumentMut) -> Result<Vec<Option<ResolvedSelector>>> {
+        self.segments
+            .iter()
+            .map(|segment| {
+                segment
+                    .selector
+                    .as_ref()
+                    .map(|selector| resolve_selector(doc, selector))
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", segment.key)?;
+            match &segment.selector {
+                None => {}
+                Some(Selector::Index(index)) => write!(f, "[{}]", index)?,
+                Some(Selector::Match { key, value }) => match value {
+                    MatchValue::Literal(literal) => write!(f, "[{}={}]", key, literal)?,
+                    MatchValue::Reference(path) => write!(f, "[{}=${}]", key, path)?,
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+enum ResolvedSelector {
+    Index(usize),
+    Match { key: String, value: String },
+}
+
+fn resolve_selector(doc: &DocumentMut, selector: &Selector) -> Result<ResolvedSelector> {
+    Ok(match selector {
+        Selector::Index(index) => ResolvedSelector::Index(*index),
+        Selector::Match { key, value } => ResolvedSelector::Match {
+            key: key.clone(),
+            value: resolve_match_value(doc, value).ok_or_else(|| match value {
+                MatchValue::Reference(path) => {
+                    anyhow::anyhow!("Referenced key '{}' is not set to a string.", path)
+                }
+                MatchValue::Literal(_) => anyhow::anyhow!("Invalid selector value."),
+            })?,
+        },
+    })
+}
+
+fn resolve_match_value(doc: &DocumentMut, value: &MatchValue) -> Option<String> {
+    match value {
+        MatchValue::Literal(literal) => Some(literal.clone()),
+        MatchValue::Reference(path) => path.get(doc)?.as_str().map(str::to_string),
+    }
+}
+
+fn find_index(doc: &DocumentMut, tables: &ArrayOfTables, selector: &Selector) -> Option<usize> {
+    match selector {
+        Selector::Index(index) => Some(*index),
+        Selector::Match { key, value } => {
+            let value = resolve_match_value(doc, value)?;
+            tables
+                .iter()
+                .position(|table| table.get(key).and_then(|v| v.as_str()) == Some(&value))
+        }
+    }
+}
+
+fn parse_selector(inner: &str) -> Result<Selector> {
+    if let Ok(index) = inner.parse::<usize>() {
+        return Ok(Selector::Index(index));
+    }
+    let (key, value) = inner.split_once('=').ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid selector '[{}]'. Use [index] or [key=value].",
+            inner
+        )
+    })?;
+    let value = match value.strip_prefix('$') {
+        Some(reference) => {
+            let path = KeyPath::parse(reference)?;
+            if path
+                .segments
+                .iter()
+                .any(|segment| segment.selector.is_some())
+            {
+                anyhow::bail!("References in selectors cannot contain selectors themselves.");
+            }
+            MatchValue::Reference(path)
+        }
+        None => MatchValue::Literal(value.trim_matches('"').to_string()),
+    };
+    Ok(Selector::Match {
+        key: key.trim().to_string(),
+        value,
+    })
+}
+
+/// Splits a path on dots that are not inside `[...]`.
+fn split_segments(path: &str) -> Result<Vec<&str>> {
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in path.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow::anyhow!("Unbalanced ']' in key path '{}'.", path))?
+            }
+            '.' if depth == 0 => {
+                segments.push(path[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        anyhow::bail!("Unclosed '[' in key path '{}'.", path);
+    }
+    segments.push(path[start..].trim());
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(content: &str) -> DocumentMut {
+        content.parse().unwrap()
+    }
+
+    fn get_str<'a>(doc: &'a DocumentMut, path: &str) -> Option<&'a str> {
+        KeyPath::parse(path).unwrap().get(doc)?.as_str()
+    }
+
+    const LEGACY: &str = r#"default_identity = "c200bbbb"
+
+[[identity_configs]]
+nickname = "first"
+identity = "c200aaaa"
+token = "token-a"
+
+[[identity_configs]]
+identity = "c200bbbb"
+token = "token-b"
+"#;
+
+    #[test]
+    fn parse_rejects_malformed_paths() {
+        for (path, message) in [
+            ("", "Empty segment"),
+            ("auth..token", "Empty segment"),
+            (".token", "Empty segment"),
+            ("configs[0", "Unclosed '['"),
+            ("configs]0].token", "Unbalanced ']'"),
+            ("configs[0]", "must end in a key"),
+            ("configs[x].token", "Invalid selector"),
+            ("configs[id=$other[0].id].token", "cannot contain selectors"),
+        ] {
+            let error = KeyPath::parse(path).unwrap_err().to_string();
+            assert!(error.contains(message), "{:?}: {}", path, error);
+        }
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for path in [
+            "spacetimedb_token",
+            "auth.token",
+            "identity_configs[0].token",
+            "identity_configs[nickname=dev].token",
+            "identity_configs[identity=$default_identity].token",
+        ] {
+            assert_eq!(KeyPath::parse(path).unwrap().to_string(), path);
+        }
+    }
+
+    #[test]
+    fn get_follows_plain_dotted_index_and_match_paths() {
+        let doc = doc(&format!(
+            "spacetimedb_token = \"plain\"\n{}\n[auth]\ntoken = \"dotted\"\n",
+            LEGACY
+        ));
+        assert_eq!(get_str(&doc, "spacetimedb_token"), Some("plain"));
+        assert_eq!(get_str(&doc, "auth.token"), Some("dotted"));
+        assert_eq!(get_str(&doc, "identity_configs[0].token"), Some("token-a"));
+        assert_eq!(
+            get_str(&doc, "identity_configs[nickname=first].token"),
+            Some("token-a")
+        );
+        assert_eq!(
+            get_str(&doc, "identity_configs[identity=$default_identity].token"),
+            Some("token-b")
+        );
+        assert_eq!(get_str(&doc, "identity_configs[5].token"), None);
+        assert_eq!(get_str(&doc, "identity_configs[nickname=none].token"), None);
+        assert_eq!(get_str(&doc, "missing.token"), None);
+    }
+
+    #[test]
+    fn set_creates_intermediate_tables() {
+        let mut doc = doc("");
+        KeyPath::parse("spacetimedb_token")
+            .unwrap()
+            .set(&mut doc, "plain")
+            .unwrap();
+        KeyPath::parse("auth.nested.token")
+            .unwrap()
+            .set(&mut doc, "dotted")
+            .unwrap();
+        assert_eq!(get_str(&doc, "spacetimedb_token"), Some("plain"));
+        assert_eq!(get_str(&doc, "auth.nested.token"), Some("dotted"));
+
+        let error = KeyPath::parse("spacetimedb_token.inner")
+            .unwrap()
+            .set(&mut doc, "x")
+            .unwrap_err();
+        assert!(error.to_string().contains("is not a table"), "{}", error);
+    }
+
+    #[test]
+    fn set_selects_or_appends_array_entries() {
+        let mut doc = doc(LEGACY);
+        KeyPath::parse("identity_configs[0].token")
+            .unwrap()
+            .set(&mut doc, "new-a")
+            .unwrap();
+        assert_eq!(get_str(&doc, "identity_configs[0].token"), Some("new-a"));
+
+        // One past the end appends, further out is an error
+        KeyPath::parse("identity_configs[2].token")
+            .unwrap()
+            .set(&mut doc, "new-c")
+            .unwrap();
+        assert_eq!(get_str(&doc, "identity_configs[2].token"), Some("new-c"));
+        let error = KeyPath::parse("identity_configs[9].token")
+            .unwrap()
+            .set(&mut doc, "x")
+            .unwrap_err();
+        assert!(error.to_string().contains("out of bounds"), "{}", error);
+
+        // A match that finds nothing appends an entry carrying the matched key
+        KeyPath::parse("identity_configs[nickname=dev].token")
+            .unwrap()
+            .set(&mut doc, "new-dev")
+            .unwrap();
+        assert_eq!(get_str(&doc, "identity_configs[3].nickname"), Some("dev"));
+        assert_eq!(get_str(&doc, "identity_configs[3].token"), Some("new-dev"));
+    }
+
+    #[test]
+    fn set_updates_the_legacy_default_identity_entry() {
+        let mut doc = doc(LEGACY);
+        let path = KeyPath::parse("identity_configs[identity=$default_identity].token").unwrap();
+        path.set(&mut doc, "rotated").unwrap();
+        assert_eq!(get_str(&doc, "identity_configs[0].token"), Some("token-a"));
+        assert_eq!(get_str(&doc, "identity_configs[1].token"), Some("rotated"));
+
+        // Without the referenced key there is nothing to match against
+        let mut doc = self::doc("[[identity_configs]]\nidentity = \"c200aaaa\"\n");
+        let error = path.set(&mut doc, "x").unwrap_err();
+        assert!(
+            error.to_string().contains("'default_identity' is not set"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn set_keeps_comments_and_formatting() {
+        let content = r#"# SpacetimeDB CLI config
+default_server = "local" # the server to use
+
+[auth]
+# managed by spacetime-token
+token = "old"
+"#;
+        let mut doc = doc(content);
+        KeyPath::parse("auth.token")
+            .unwrap()
+            .set(&mut doc, "new")
+            .unwrap();
+        assert_eq!(doc.to_string(), content.replace("\"old\"", "\"new\""));
+    }
+
+    #[test]
+    fn remove_deletes_only_the_addressed_key() {
+        let mut doc = doc(LEGACY);
+        let path = KeyPath::parse("identity_configs[identity=$default_identity].token").unwrap();
+        assert!(path.remove(&mut doc));
+        assert!(!path.remove(&mut doc));
+        assert_eq!(
+            get_str(&doc, "identity_configs[1].identity"),
+            Some("c200bbbb")
+        );
+        assert_eq!(get_str(&doc, "identity_configs[0].token"), Some("token-a"));
+
+        assert!(KeyPath::parse("default_identity").unwrap().remove(&mut doc));
+        assert!(!KeyPath::parse("auth.token").unwrap().remove(&mut doc));
+        assert!(!KeyPath::parse("identity_configs[7].token")
+            .unwrap()
+            .remove(&mut doc));
+    }
+}
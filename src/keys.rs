@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+};
+use rand_core::OsRng;
+use std::{fs, io::Write, path::Path};
+
+/// An ES256 (P-256) key pair used to sign locally minted tokens.
+pub struct IssuerKey {
+    signing_key: SigningKey,
+}
+
+impl IssuerKey {
+    pub fn generate() -> Self {
+        Self {
+            signing_key: SigningKey::random(&mut OsRng),
+        }
+    }
+
+    /// Loads a PKCS#8 PEM private key.
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read private key at {:?}", path))?;
+        let signing_key = SigningKey::from_pkcs8_pem(&pem).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to parse {:?} as a PKCS#8 P-256 private key: {}",
+                path,
+                e
+            )
+        })?;
+        Ok(Self { signing_key })
+    }
+
+    /// Writes the private key as PKCS#8 PEM, readable only by the current user.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let pem = self
+            .signing_key
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| anyhow::anyhow!("Failed to encode private key: {}", e))?;
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir)
+                .with_context(|| format!("Failed to create directory {:?}", parent_dir))?;
+        }
+        write_private(path, pem.as_bytes())
+            .with_context(|| format!("Failed to write private key to {:?}", path))
+    }
+
+    /// A stable key id derived from the public key.
+    pub fn key_id(&self) -> String {
+        let public_key = self.signing_key.verifying_key().to_encoded_point(true);
+        to_hex(&blake3::hash(public_key.as_bytes()).as_bytes()[..8])
+    }
+
+    /// The public key as a JWK, suitable for a JWKS endpoint.
+    pub fn public_jwk(&self) -> serde_json::Value {
+        let jwk = p256::PublicKey::from(self.signing_key.verifying_key()).to_jwk();
+        let mut value = serde_json::to_value(&jwk).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.insert("kid".into(), self.key_id().into());
+            object.insert("alg".into(), "ES256".into());
+            object.insert("use".into(), "sig".into());
+        }
+        value
+    }
+
+    /// Signs `claims` as a compact ES256 JWT.
+    pub fn sign_jwt(&self, claims: &serde_json::Value) -> Result<String> {
+        let header = serde_json::json!({
+            "alg": "ES256",
+            "typ": "JWT",
+            "kid": self.key_id(),
+        });
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+}
+
+/// Writes a file holding secrets (keys, tokens) that only the current user
+/// may read. A replaced file loses any wider permissions it had.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create {:?}", path))?;
+    // `mode` only applies to new files; tighten a replaced one before writing
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict permissions of {:?}", path))?;
+    }
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the environment variables that override single settings,
+/// e.g. `STT_CLI_TOKEN_KEY` for `cli_token_key`
+pub const ENV_PREFIX: &str = "STT_";
+const PROJECT_CONFIG_FILENAME: &str = "config.toml";
+
+/// The settings a project `.stt/config.toml` may change. A cloned repository
+/// is not trusted, so settings that pick a program to run (`spacetime_command`)
+/// or a place to write tokens (`targets`, the file and directory names) are
+/// left to the system and user files.
+pub const PROJECT_KEYS: &[&str] = &["cli_token_key", "default_login_method"];
+
+/// Where a layer of settings (and so each merged value) came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(name) => write!(f, "env:{}", name),
+        }
+    }
+}
+
+/// One source of settings. Later layers override earlier ones key by key.
+#[derive(Debug)]
+pub struct Layer {
+    pub origin: Origin,
+    pub table: toml::Table,
+}
+
+impl Layer {
+    /// Reads a settings file, or returns `None` if it does not exist.
+    pub fn from_file(path: PathBuf) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read app config file at {:?}", path))?;
+        let table = content
+            .parse::<toml::Table>()
+            .with_context(|| format!("Failed to parse app config file at {:?}", path))?;
+        Ok(Some(Self {
+            origin: Origin::File(path),
+            table,
+        }))
+    }
+
+    /// Collects `STT_<KEY>` overrides for `keys` from `env`, the process
+    /// environment as (name, value) pairs. `defaults` decides how a value is
+    /// read: as plain text where the default is a string, as a TOML value
+    /// otherwise.
+    pub fn from_env(
+        keys: &[String],
+        defaults: &toml::Table,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Vec<Self>> {
+        let mut env: BTreeMap<String, String> = env.into_iter().collect();
+        let mut layers = Vec::new();
+        for key in keys {
+            let name = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+            let Some(raw) = env.remove(&name) else {
+                continue;
+            };
+            let value = if defaults.get(key).is_some_and(toml::Value::is_str) {
+                toml::Value::String(raw)
+            } else {
+                format!("value = {}", raw)
+                    .parse::<toml::Table>()
+                    .ok()
+                    .and_then(|mut parsed| parsed.remove("value"))
+                    .with_context(|| format!("{} is not a valid TOML value.", name))?
+            };
+            layers.push(Self {
+                origin: Origin::Env(name),
+                table: toml::Table::from_iter([(key.clone(), value)]),
+            });
+        }
+        Ok(layers)
+    }
+
+    /// Drops every key not in `allowed`, returning the dropped keys.
+    pub fn retain_keys(&mut self, allowed: &[&str]) -> Vec<String> {
+        let ignored: Vec<String> = self
+            .table
+            .keys()
+            .filter(|key| !allowed.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        for key in &ignored {
+            self.table.remove(key);
+        }
+        ignored
+    }
+}
+
+/// Merges `layers` in order and records which layer each key came from.
+pub fn merge(layers: &[Layer]) -> (toml::Table, BTreeMap<String, Origin>) {
+    let mut merged = toml::Table::new();
+    let mut origins = BTreeMap::new();
+    for layer in layers {
+        for (key, value) in &layer.table {
+            merged.insert(key.clone(), value.clone());
+            origins.insert(key.clone(), layer.origin.clone());
+        }
+    }
+    (merged, origins)
+}
+
+/// Finds the nearest `.stt/config.toml` in `start` or one of its parent directories.
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| {
+            dir.join(crate::pin::PROJECT_DIR_NAME)
+                .join(PROJECT_CONFIG_FILENAME)
+        })
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stt-layers-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn layer(origin: Origin, content: &str) -> Layer {
+        Layer {
+            origin,
+            table: content.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones_per_key() {
+        let user = Origin::File(PathBuf::from("/user/config.toml"));
+        let (merged, origins) = merge(&[
+            layer(Origin::Default, "a = \"default\"\nb = \"default\""),
+            layer(user.clone(), "b = \"user\""),
+        ]);
+        assert_eq!(merged["a"].as_str(), Some("default"));
+        assert_eq!(merged["b"].as_str(), Some("user"));
+        assert_eq!(origins["a"], Origin::Default);
+        assert_eq!(origins["b"], user);
+    }
+
+    #[test]
+    fn project_config_cannot_pick_a_binary_or_token_destination() {
+        let dir = temp_dir("project");
+        let project_dir = dir.join("repo").join(crate::pin::PROJECT_DIR_NAME);
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(
+            project_dir.join(PROJECT_CONFIG_FILENAME),
+            r#"
+cli_token_key = "auth.token"
+spacetime_command = "./evil.sh"
+cli_config_dir_from_home = "/tmp/repo"
+profiles_filename = "/tmp/repo/profiles.toml"
+
+[[targets]]
+path = "tracked.env"
+format = "dotenv"
+key = "SPACETIMEDB_TOKEN"
+"#,
+        )
+        .unwrap();
+
+        let nested = dir.join("repo").join("src");
+        fs::create_dir_all(&nested).unwrap();
+        let path = find_project_config(&nested).unwrap();
+        let mut project = Layer::from_file(path).unwrap().unwrap();
+        let mut ignored = project.retain_keys(PROJECT_KEYS);
+        ignored.sort();
+        assert_eq!(
+            ignored,
+            [
+                "cli_config_dir_from_home",
+                "profiles_filename",
+                "spacetime_command",
+                "targets"
+            ]
+        );
+
+        let (merged, _) = merge(&[
+            layer(Origin::Default, "spacetime_command = \"spacetime\""),
+            project,
+        ]);
+        assert_eq!(merged["spacetime_command"].as_str(), Some("spacetime"));
+        assert_eq!(merged["cli_token_key"].as_str(), Some("auth.token"));
+        assert!(!merged.contains_key("targets"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn env_values_follow_the_type_of_the_default() {
+        let defaults: toml::Table = "name = \"x\"".parse().unwrap();
+        let keys = ["name".to_string(), "list".to_string(), "other".to_string()];
+        let env = [
+            ("STT_NAME", "[1, 2]"),
+            ("STT_LIST", "[1, 2]"),
+            ("NAME", "ignored"),
+            ("STT_UNKNOWN", "ignored"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let layers = Layer::from_env(&keys, &defaults, env).unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].table["name"].as_str(), Some("[1, 2]"));
+        assert_eq!(layers[0].origin, Origin::Env("STT_NAME".to_string()));
+        assert!(layers[1].table["list"].is_array());
+
+        let invalid = [("STT_LIST".to_string(), "[1,".to_string())];
+        let error = Layer::from_env(&keys, &defaults, invalid).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("STT_LIST is not a valid TOML value"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,21 +1,125 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Select};
+use clap::{Parser, ValueEnum};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Password, Select};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::PathBuf, process::Command as StdCommand};
-use toml_edit::{DocumentMut, Item};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    process::Command as StdCommand,
+};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+mod api;
+mod backup;
+mod cli_version;
+mod discovery;
+mod issuer;
+mod key_path;
+mod keys;
+mod layers;
+mod pin;
+mod schema;
+mod shell;
+mod snippet;
+#[cfg(test)]
+mod stub_server;
+mod targets;
+mod token;
 
 const APP_DIR_NAME: &str = "spacetime-token"; // Renamed
 const DEFAULT_PROFILES_FILENAME: &str = "profiles.toml"; // Renamed
 const DEFAULT_CONFIG_FILENAME: &str = "config.toml";
+/// Machine-wide settings, overridden by the user's config.toml
+const SYSTEM_CONFIG_PATH: &str = "/etc/spacetime-token/config.toml";
+const DEFAULT_CONTEXTS_FILENAME: &str = "contexts.toml";
+const BACKUPS_DIR_NAME: &str = "backups";
+const ISSUER_DIR_NAME: &str = "issuer";
+const ISSUER_KEY_FILENAME: &str = "signing_key.pem";
 const SPACETIME_CLI_COMMAND: &str = "spacetime";
+/// Session variable naming the profile this terminal uses (see `env`)
+const PROFILE_ENV_VAR: &str = "STT_PROFILE";
+/// Session variable `env` exports the profile's token in, for client apps
+const TOKEN_ENV_VAR: &str = "SPACETIMEDB_TOKEN";
+
+const DEFAULT_LOGIN_SERVER: &str = "local";
+/// Servers the SpacetimeDB CLI knows about even without a `server_configs` entry
+const BUILTIN_SERVERS: &[(&str, &str)] = &[
+    ("local", "http://127.0.0.1:3000"),
+    ("maincloud", "https://maincloud.spacetimedb.com"),
+];
 
+// Every field falls back to its default, so files that leave settings out
+// (older ones, or partial system and project layers) still parse
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 struct AppSettings {
     profiles_filename: String, // Renamed
     cli_config_dir_from_home: String,
     cli_config_filename: String,
     cli_token_key: String,
+    default_login_method: LoginMethod,
+    contexts_filename: String,
+    /// Extra files that receive the active token alongside cli.toml
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    targets: Vec<targets::TokenTarget>,
+    /// Name (looked up in PATH) or path of the real SpacetimeDB CLI
+    spacetime_command: String,
+}
+
+/// Upgrades of config.toml. Entry `n` takes a file from schema version `n`
+/// to `n + 1`, so the current version is the length of the list.
+const CONFIG_MIGRATIONS: &[schema::Migration] = &[
+    // 0 -> 1: versioning introduced; the settings themselves are unchanged
+    |_| Ok(()),
+];
+
+/// Upgrades of profiles.toml, like `CONFIG_MIGRATIONS`.
+const PROFILES_MIGRATIONS: &[schema::Migration] = &[
+    // 0 -> 1: versioning introduced; `schema_version` is no longer a valid profile name
+    |_| Ok(()),
+];
+
+/// The fields of `AppSettings`, as accepted by `config get/set/unset`.
+/// A test checks that the two list the same names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+enum SettingKey {
+    ProfilesFilename,
+    CliConfigDirFromHome,
+    CliConfigFilename,
+    CliTokenKey,
+    DefaultLoginMethod,
+    ContextsFilename,
+    Targets,
+    SpacetimeCommand,
+}
+
+impl SettingKey {
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+fn default_contexts_filename() -> String {
+    DEFAULT_CONTEXTS_FILENAME.to_string()
+}
+
+fn default_spacetime_command() -> String {
+    SPACETIME_CLI_COMMAND.to_string()
+}
+
+/// How `create` obtains a new token from the SpacetimeDB CLI
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+enum LoginMethod {
+    /// `spacetime login --server-issued-login <server>`
+    #[default]
+    ServerIssued,
+    /// `spacetime login` through the browser
+    Web,
 }
 
 impl Default for AppSettings {
@@ -25,6 +129,10 @@ impl Default for AppSettings {
             cli_config_dir_from_home: ".config/spacetime".to_string(),
             cli_config_filename: "cli.toml".to_string(),
             cli_token_key: "spacetimedb_token".to_string(),
+            default_login_method: LoginMethod::default(),
+            contexts_filename: default_contexts_filename(),
+            targets: Vec::new(),
+            spacetime_command: default_spacetime_command(),
         }
     }
 }
@@ -47,21 +155,166 @@ enum Commands {
     /// Saves the current active token from cli.toml to a new profile name
     Save(SaveArgs),
     /// Resets (clears) the profiles.toml file
-    Reset,
+    Reset(ResetArgs),
     /// Creates a new profile via 'spacetime login' and saves the token
     Create(CreateArgs),
     /// Lists all stored profile names
-    List,
+    List(ListArgs),
     /// Deletes a stored profile
     Delete(DeleteArgs),
-    /// Interactive setup for configuration values
+    /// Guided setup: finds the SpacetimeDB CLI and cli.toml, then checks and saves the settings
     Setup,
     /// Switches the active token to a stored profile
     Switch(SwitchArgs),
     /// Displays the current active profile name and token (masked)
     Current,
     /// Switches to the admin profile
-    Admin,
+    Admin(AdminArgs),
+    /// Re-runs a profile's login flow and updates its token in place
+    Refresh(RefreshArgs),
+    /// Creates a new identity over the server's HTTP API and saves its token
+    NewIdentity(NewIdentityArgs),
+    /// Manages pools of identities for multi-client testing
+    #[clap(subcommand)]
+    Pool(PoolCommands),
+    /// Checks the stored tokens (expiry offline, or with the server using --online)
+    Check(CheckArgs),
+    /// Lists the databases owned by a profile's identity
+    Databases(DatabasesArgs),
+    /// Restores profiles from an encrypted backup
+    Restore(RestoreArgs),
+    /// Exchanges a profile's token for a short-lived websocket token
+    WsToken(WsTokenArgs),
+    /// Generates an ES256 key pair for signing locally minted tokens
+    Keygen(KeygenArgs),
+    /// Mints a signed token locally and saves it as a profile
+    Mint(MintArgs),
+    /// Local OIDC issuer stand-in for locally minted tokens
+    #[clap(subcommand)]
+    Issuer(IssuerCommands),
+    /// Manages contexts (a profile, a server and an optional default database)
+    #[clap(subcommand)]
+    Context(ContextCommands),
+    /// Runs a spacetime command, adding the current context's database where needed
+    Exec(ExecArgs),
+    /// Manages the server nicknames in cli.toml
+    #[clap(subcommand)]
+    Server(ServerCommands),
+    /// Prints a client SDK connection snippet for a profile
+    Snippet(SnippetArgs),
+    /// Runs a spacetime command once per profile, each with its own isolated config
+    Foreach(ForeachArgs),
+    /// Pins a profile to the current directory for the `spacetime` shim
+    Pin(PinArgs),
+    /// Manages the `spacetime` shim that runs the real CLI as the selected profile
+    #[clap(subcommand)]
+    Shim(ShimCommands),
+    /// Prints shell commands that select a profile for this terminal only
+    Env(EnvArgs),
+    /// Prints the profile in use, for embedding in a shell prompt
+    Prompt,
+    /// Shows or overrides which SpacetimeDB CLI binary is used
+    #[clap(subcommand)]
+    Binary(BinaryCommands),
+    /// Lists the places the SpacetimeDB CLI may keep its config and which of them exist
+    Discover,
+    /// Imports the tokens of a pre-0.12 SpacetimeDB config as profiles
+    Migrate(MigrateArgs),
+    /// Reads and changes the tool's settings without prompts
+    #[clap(subcommand)]
+    Config(ConfigCommands),
+}
+
+#[derive(Parser, Debug)]
+enum ConfigCommands {
+    /// Prints the value of a setting
+    Get(ConfigKeyArgs),
+    /// Changes a setting
+    Set(ConfigSetArgs),
+    /// Resets a setting to its default
+    Unset(ConfigKeyArgs),
+    /// Prints every setting
+    List(ConfigListArgs),
+    /// Prints the path of the user config.toml
+    Path,
+}
+
+#[derive(Parser, Debug)]
+struct ConfigKeyArgs {
+    #[clap(value_enum)]
+    key: SettingKey,
+}
+
+#[derive(Parser, Debug)]
+struct ConfigListArgs {
+    /// Shows which file or environment variable each value came from
+    #[clap(long)]
+    show_origin: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ConfigSetArgs {
+    #[clap(value_enum)]
+    key: SettingKey,
+    value: String,
+}
+
+#[derive(Parser, Debug)]
+enum BinaryCommands {
+    /// Shows the resolved SpacetimeDB CLI, its version and whether cli_token_key fits it
+    Show(BinaryShowArgs),
+    /// Makes a profile use a specific SpacetimeDB CLI
+    Bind(BinaryBindArgs),
+}
+
+#[derive(Parser, Debug)]
+enum ShimCommands {
+    /// Links a `spacetime` shim into a directory that comes before the real CLI in PATH
+    Install(ShimInstallArgs),
+    /// Shows which profile and binary the shim would use here
+    Status,
+}
+
+#[derive(Parser, Debug)]
+enum ServerCommands {
+    /// Adds a server nickname to cli.toml
+    Add(ServerAddArgs),
+    /// Lists the servers known to cli.toml
+    List,
+    /// Removes a server nickname from cli.toml
+    Remove(ServerRemoveArgs),
+    /// Sets cli.toml's default_server
+    Default(ServerDefaultArgs),
+    /// Binds a profile to a server nickname
+    Bind(ServerBindArgs),
+}
+
+#[derive(Parser, Debug)]
+enum ContextCommands {
+    /// Creates (or replaces) a context
+    Create(ContextCreateArgs),
+    /// Activates a context: writes its token and default_server into cli.toml
+    Use(ContextUseArgs),
+    /// Lists all contexts
+    List,
+    /// Deletes a context
+    Delete(ContextDeleteArgs),
+}
+
+#[derive(Parser, Debug)]
+enum IssuerCommands {
+    /// Serves the OpenID discovery document and JWKS on localhost
+    Serve(IssuerServeArgs),
+    /// Prints the JWKS of the issuer key
+    Jwks,
+}
+
+#[derive(Parser, Debug)]
+enum PoolCommands {
+    /// Mints identities over HTTP, saves them as profiles and exports a JSON fixture
+    Create(PoolCreateArgs),
+    /// Deletes every profile that belongs to a pool
+    Delete(PoolDeleteArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -70,12 +323,30 @@ struct SetArgs {
     profile_name: String, // Renamed
     /// The token to associate with the profile name
     token: String,
+    #[clap(flatten)]
+    activation: ActivationArgs,
+}
+
+/// Options shared by the commands that make a token active
+#[derive(Parser, Debug)]
+struct ActivationArgs {
+    /// Activate the token even if its issuer does not match the server
+    #[clap(long)]
+    allow_mismatch: bool,
+}
+
+#[derive(Parser, Debug)]
+struct AdminArgs {
+    #[clap(flatten)]
+    activation: ActivationArgs,
 }
 
 #[derive(Parser, Debug)]
 struct SwitchArgs {
     /// The profile name of the stored profile to make active (optional)
     profile_name: Option<String>, // Renamed
+    #[clap(flatten)]
+    activation: ActivationArgs,
 }
 
 #[derive(Parser, Debug)]
@@ -88,16 +359,587 @@ struct SaveArgs {
 struct CreateArgs {
     /// The profile name for the new profile
     profile_name: String, // Renamed
+    /// Server nickname (or URL) to log in against [default: local]
+    #[clap(long)]
+    server: Option<String>,
+    /// Log in through the browser instead of a server-issued identity
+    #[clap(long)]
+    web: bool,
+    /// Use a server-issued identity even if the configured default is web login
+    #[clap(long, conflicts_with = "web")]
+    server_issued: bool,
+    /// Skip 'spacetime logout' before logging in
+    #[clap(long)]
+    no_logout: bool,
+    /// Extra arguments passed through to 'spacetime login' (after '--')
+    #[clap(last = true)]
+    login_args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct RefreshArgs {
+    /// The profile name of the profile to refresh
+    #[clap(required_unless_present = "all_expired")]
+    profile_name: Option<String>,
+    /// Refresh every profile whose token has expired
+    #[clap(long, conflicts_with = "profile_name")]
+    all_expired: bool,
+}
+
+#[derive(Parser, Debug)]
+struct NewIdentityArgs {
+    /// The profile name for the new identity
+    profile_name: String,
+    /// Server URL or cli.toml nickname [default: cli.toml's default_server]
+    #[clap(long)]
+    server: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct PoolCreateArgs {
+    /// Prefix for the profile names (profiles are named <prefix>-001, <prefix>-002, ...)
+    prefix: String,
+    /// Number of identities to create
+    #[clap(long)]
+    count: usize,
+    /// Server URL or cli.toml nickname [default: cli.toml's default_server]
+    #[clap(long)]
+    server: Option<String>,
+    /// Extra tag to add to every profile in the pool
+    #[clap(long)]
+    tag: Option<String>,
+    /// Path of the JSON fixture to write [default: <prefix>.json]
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct PoolDeleteArgs {
+    /// Prefix the pool was created with
+    prefix: String,
+    #[clap(flatten)]
+    removal: RemovalArgs,
+}
+
+#[derive(Parser, Debug)]
+struct CheckArgs {
+    /// Only check this profile
+    profile_name: Option<String>,
+    /// Ask the server whether it still accepts each token
+    #[clap(long)]
+    online: bool,
+    /// Server URL or cli.toml nickname to check against [default: each profile's server]
+    #[clap(long, requires = "online")]
+    server: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ListArgs {
+    /// Also ask each profile's server how many databases its identity owns
+    #[clap(long)]
+    with_databases: bool,
+}
+
+#[derive(Parser, Debug)]
+struct DatabasesArgs {
+    /// The profile whose databases to list [default: the active profile]
+    profile_name: Option<String>,
 }
 
 #[derive(Parser, Debug)]
 struct DeleteArgs {
     /// The profile name of the profile to delete
     profile_name: String, // Renamed
+    #[clap(flatten)]
+    removal: RemovalArgs,
+}
+
+#[derive(Parser, Debug)]
+struct ResetArgs {
+    #[clap(flatten)]
+    removal: RemovalArgs,
+}
+
+/// Safety options shared by the commands that throw tokens away
+#[derive(Parser, Debug)]
+struct RemovalArgs {
+    /// Remove profiles even if their identities still own databases, without prompting
+    #[clap(long)]
+    force: bool,
+    /// Look up owned databases on the server even if cached metadata exists
+    #[clap(long)]
+    online: bool,
+}
+
+#[derive(Parser, Debug)]
+struct WsTokenArgs {
+    /// The profile whose token to exchange
+    profile_name: String,
+    /// Server URL or cli.toml nickname [default: the profile's server]
+    #[clap(long)]
+    server: Option<String>,
+    /// Print the result as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct WsTokenOutput<'a> {
+    profile: &'a str,
+    server: &'a str,
+    token: &'a str,
+}
+
+#[derive(Parser, Debug)]
+struct KeygenArgs {
+    /// Where to write the private key [default: issuer/signing_key.pem in the config directory]
+    #[clap(long)]
+    out: Option<PathBuf>,
+    /// Overwrite an existing key
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct MintArgs {
+    /// The profile name to save the minted token under
+    profile_name: String,
+    /// Issuer (`iss` claim) of the token
+    #[clap(long)]
+    issuer: String,
+    /// Subject (`sub` claim) of the token
+    #[clap(long)]
+    sub: String,
+    /// PKCS#8 PEM private key to sign with [default: the key created by 'keygen']
+    #[clap(long)]
+    key: Option<PathBuf>,
+    /// Lifetime of the token, e.g. 3600, 30m, 12h or 7d [default: no expiry]
+    #[clap(long)]
+    ttl: Option<String>,
+    /// Audience (`aud` claim) of the token
+    #[clap(long)]
+    audience: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct IssuerServeArgs {
+    /// Port to listen on
+    #[clap(long, default_value_t = 4000)]
+    port: u16,
+    /// Address to bind to
+    #[clap(long, default_value = "127.0.0.1")]
+    bind: String,
+    /// Public URL of the issuer, used as `iss` [default: http://localhost:<port>]
+    #[clap(long)]
+    issuer_url: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ContextCreateArgs {
+    /// The context name
+    context_name: String,
+    /// Profile whose token the context uses
+    #[clap(long)]
+    profile: String,
+    /// Server nickname from cli.toml [default: the profile's server]
+    #[clap(long)]
+    server: Option<String>,
+    /// Default database for 'exec'
+    #[clap(long)]
+    database: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ContextUseArgs {
+    /// The context to activate
+    context_name: String,
+    #[clap(flatten)]
+    activation: ActivationArgs,
+}
+
+#[derive(Parser, Debug)]
+struct ContextDeleteArgs {
+    /// The context to delete
+    context_name: String,
+}
+
+#[derive(Parser, Debug)]
+struct ExecArgs {
+    /// Never insert the current context's database
+    #[clap(long)]
+    no_database: bool,
+    /// Arguments passed to spacetime (after '--')
+    #[clap(last = true, required = true)]
+    spacetime_args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct SnippetArgs {
+    /// The profile whose token and server to use
+    profile_name: String,
+    /// Language of the snippet
+    #[clap(long, value_enum)]
+    lang: snippet::SnippetLang,
+    /// Module (database) name [default: the current context's database]
+    #[clap(long)]
+    module: Option<String>,
+    /// Server URL or cli.toml nickname [default: the profile's server]
+    #[clap(long)]
+    server: Option<String>,
+    /// Read the token from an environment variable instead of embedding it
+    #[clap(long, value_name = "VAR", num_args = 0..=1, default_missing_value = snippet::DEFAULT_TOKEN_ENV_VAR)]
+    token_env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(clap::ArgGroup::new("selection").required(true).args(&["tag", "all"])))]
+struct ForeachArgs {
+    /// Run as every profile with this tag
+    #[clap(long)]
+    tag: Option<String>,
+    /// Run as every profile
+    #[clap(long)]
+    all: bool,
+    /// How many profiles to run at the same time
+    #[clap(long, short, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: u32,
+    /// Arguments passed to spacetime (after '--')
+    #[clap(last = true, required = true)]
+    spacetime_args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct PinArgs {
+    /// The profile to pin [default: show the pin that applies here]
+    profile_name: Option<String>,
+    /// Remove the pin from the current directory
+    #[clap(long, conflicts_with = "profile_name")]
+    clear: bool,
+}
+
+#[derive(Parser, Debug)]
+struct EnvArgs {
+    /// The profile to use in this terminal
+    #[clap(required_unless_present = "unset")]
+    profile_name: Option<String>,
+    /// Print commands that clear the session profile instead
+    #[clap(long, conflicts_with = "profile_name")]
+    unset: bool,
+    /// Shell syntax to print
+    #[clap(long, value_enum, default_value_t)]
+    shell: shell::Shell,
+}
+
+#[derive(Parser, Debug)]
+struct MigrateArgs {
+    /// Legacy config file to read [default: the first one found by `discover`]
+    #[clap(long)]
+    from: Option<PathBuf>,
+    /// Only show what would be imported
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+struct BinaryShowArgs {
+    /// Show the binary this profile uses [default: the global one]
+    profile_name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct BinaryBindArgs {
+    /// The profile to bind
+    profile_name: String,
+    /// Command name (looked up in PATH) or path of the SpacetimeDB CLI
+    #[clap(required_unless_present = "clear")]
+    command: Option<String>,
+    /// Remove the profile's override instead
+    #[clap(long, conflicts_with = "command")]
+    clear: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ShimInstallArgs {
+    /// Directory to create the `spacetime` link in
+    dir: PathBuf,
+    /// Replace an existing file
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ServerAddArgs {
+    /// Nickname for the server
+    nickname: String,
+    /// URL of the server, e.g. http://127.0.0.1:3000
+    url: String,
+    /// Also make it the default server
+    #[clap(long)]
+    default: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ServerRemoveArgs {
+    /// Nickname of the server to remove
+    nickname: String,
+}
+
+#[derive(Parser, Debug)]
+struct ServerDefaultArgs {
+    /// Nickname of the server to make the default
+    nickname: String,
+}
+
+#[derive(Parser, Debug)]
+struct ServerBindArgs {
+    /// The profile to bind
+    profile_name: String,
+    /// Server nickname (or URL) to bind the profile to
+    #[clap(required_unless_present = "clear")]
+    nickname: Option<String>,
+    /// Remove the profile's server binding instead
+    #[clap(long, conflicts_with = "nickname")]
+    clear: bool,
+}
+
+#[derive(Parser, Debug)]
+struct RestoreArgs {
+    /// Path of the backup file to restore
+    backup_file: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct UserProfiles(#[serde(with = "profile_entries")] HashMap<String, Profile>); // Renamed
+
+/// A stored profile. Profiles without metadata are written as a plain token
+/// string so existing `profiles.toml` files keep their `name = "token"` shape.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct Profile {
+    token: String,
+    /// Server nickname (or URL) the profile was created against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    /// The login flow used to obtain the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    login: Option<LoginFlow>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Unix time of the last successful `check --online`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_verified: Option<u64>,
+    /// Databases owned by the identity, as of the last lookup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    databases: Option<Vec<OwnedDatabase>>,
+    /// SpacetimeDB CLI to use for this profile instead of the global `spacetime_command`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spacetime_command: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct OwnedDatabase {
+    identity: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    names: Vec<String>,
 }
 
+impl Profile {
+    fn new(token: String) -> Self {
+        Self {
+            token,
+            ..Self::default()
+        }
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+/// Named combinations of a profile, a server and a default database,
+/// stored in the contexts file next to the profiles.
 #[derive(Serialize, Deserialize, Debug, Default)]
-struct UserProfiles(HashMap<String, String>); // Renamed
+struct Contexts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    #[serde(default)]
+    contexts: HashMap<String, ContextEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ContextEntry {
+    profile: String,
+    server: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    database: Option<String>,
+}
+
+/// spacetime subcommands whose first positional argument is a database, with
+/// the number of positional arguments they require including the database
+const DATABASE_SUBCOMMANDS: &[(&str, usize)] = &[
+    ("call", 2),
+    ("sql", 2),
+    ("logs", 1),
+    ("describe", 1),
+    ("delete", 1),
+    ("subscribe", 2),
+];
+
+/// spacetime options that take a separate value, so that value is not
+/// counted as a positional argument
+const VALUE_OPTIONS: &[&str] = &[
+    "-s",
+    "--server",
+    "-i",
+    "--identity",
+    "-n",
+    "--num-lines",
+    "--num-updates",
+    "-t",
+    "--timeout",
+    "-p",
+    "--project-path",
+    "-b",
+    "--bin-path",
+    "--format",
+    "--level",
+    "--build-options",
+];
+
+/// Counts the positional arguments after the subcommand in `args`.
+fn positional_count(args: &[String]) -> usize {
+    let mut count = 0;
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--" {
+            return count + rest.count();
+        }
+        if VALUE_OPTIONS.contains(&arg.as_str()) {
+            rest.next();
+        } else if !arg.starts_with('-') || arg == "-" {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Adds `database` to spacetime arguments that need one and don't name one yet:
+/// as the first argument of the subcommands in [`DATABASE_SUBCOMMANDS`], and
+/// at the end for `publish`.
+fn insert_context_database(args: &mut Vec<String>, database: String) {
+    let Some(subcommand) = args.first().map(String::as_str) else {
+        return;
+    };
+    if subcommand == "publish" {
+        if positional_count(args) == 0 {
+            args.push(database);
+        }
+        return;
+    }
+    let Some(&(_, required)) = DATABASE_SUBCOMMANDS
+        .iter()
+        .find(|(name, _)| *name == subcommand)
+    else {
+        return;
+    };
+    if positional_count(args) < required {
+        args.insert(1, database);
+    }
+}
+
+/// Tag shared by every profile created by `pool create <prefix>`
+fn pool_tag(prefix: &str) -> String {
+    format!("pool:{}", prefix)
+}
+
+/// An entry in the JSON fixture written by `pool create`
+#[derive(Serialize, Debug)]
+struct PoolMember {
+    name: String,
+    identity: String,
+    token: String,
+}
+
+mod profile_entries {
+    use super::Profile;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum ProfileEntry {
+        Token(String),
+        Full(Profile),
+    }
+
+    pub fn serialize<S: Serializer>(
+        profiles: &HashMap<String, Profile>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let entries: HashMap<&String, ProfileEntry> = profiles
+            .iter()
+            .map(|(name, profile)| {
+                let entry = if *profile == Profile::new(profile.token.clone()) {
+                    ProfileEntry::Token(profile.token.clone())
+                } else {
+                    ProfileEntry::Full(profile.clone())
+                };
+                (name, entry)
+            })
+            .collect();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, Profile>, D::Error> {
+        let entries = HashMap::<String, ProfileEntry>::deserialize(deserializer)?;
+        Ok(entries
+            .into_iter()
+            .map(|(name, entry)| match entry {
+                ProfileEntry::Token(token) => (name, Profile::new(token)),
+                ProfileEntry::Full(profile) => (name, profile),
+            })
+            .collect())
+    }
+}
+
+/// The `spacetime logout`/`spacetime login` sequence used to obtain a token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct LoginFlow {
+    method: LoginMethod,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    no_logout: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_args: Vec<String>,
+}
+
+impl LoginFlow {
+    /// The flow used for profiles that were not created with `create`.
+    fn fallback(settings: &AppSettings, server: Option<String>) -> Self {
+        Self {
+            method: settings.default_login_method,
+            server,
+            no_logout: false,
+            extra_args: Vec::new(),
+        }
+    }
+
+    fn login_args(&self) -> Vec<String> {
+        let mut args = vec!["login".to_string()];
+        if self.method == LoginMethod::ServerIssued {
+            args.push("--server-issued-login".to_string());
+            args.push(
+                self.server
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_LOGIN_SERVER.to_string()),
+            );
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
 
 fn get_app_config_dir() -> Result<PathBuf> {
     let config_dir = dirs::config_dir()
@@ -107,98 +949,412 @@ fn get_app_config_dir() -> Result<PathBuf> {
         fs::create_dir_all(&config_dir).with_context(|| {
             format!("Failed to create app config directory at {:?}", config_dir)
         })?;
-        println!("Created application config directory at {:?}", config_dir);
+        // First-run notices go to stderr: this also runs as the `spacetime`
+        // shim and under `eval "$(stt env ...)"`, where stdout is not ours
+        eprintln!("Created application config directory at {:?}", config_dir);
     }
     Ok(config_dir)
 }
 
 fn load_app_settings() -> Result<AppSettings> {
-    let app_config_dir = get_app_config_dir()?;
-    let config_file_path = app_config_dir.join(DEFAULT_CONFIG_FILENAME);
+    prepare_user_config()?;
+    load_layered_settings().map(|(settings, _)| settings)
+}
+
+/// Creates the user config.toml on first run and upgrades an older one.
+fn prepare_user_config() -> Result<()> {
+    let config_file_path = get_app_settings_path()?;
 
     if !config_file_path.exists() {
-        println!(
-            "Configuration file not found at {:?}. Creating with default settings.",
+        eprintln!(
+            "Configuration file not found at {:?}. Creating it with the defaults commented out.",
             config_file_path
         );
-        let default_settings = AppSettings::default();
-        let toml_content = toml::to_string_pretty(&default_settings)
+        // Commented out so system-wide settings are not shadowed by a copy of the defaults
+        let defaults = toml::to_string_pretty(&AppSettings::default())
             .context("Failed to serialize default settings to TOML")?;
-        fs::write(&config_file_path, toml_content)
-            .with_context(|| format!("Failed to write default config to {:?}", config_file_path))?;
-        return Ok(default_settings);
+        let mut toml_content = String::from(
+            "# Uncomment a setting to override the system-wide value or the default.\n",
+        );
+        for line in defaults.lines() {
+            toml_content.push_str(&format!("# {}\n", line).replace("# \n", "\n"));
+        }
+        fs::write(
+            &config_file_path,
+            schema::stamp(&toml_content, CONFIG_MIGRATIONS.len() as u32),
+        )
+        .with_context(|| format!("Failed to write default config to {:?}", config_file_path))?;
     }
+    schema::upgrade(&config_file_path, CONFIG_MIGRATIONS, &get_backups_dir()?)
+}
 
-    let content = fs::read_to_string(&config_file_path)
-        .with_context(|| format!("Failed to read app config file at {:?}", config_file_path))?;
-    toml::from_str(&content)
-        .with_context(|| format!("Failed to parse app config file at {:?}", config_file_path))
+/// The settings sources in merge order: the defaults, the system file, the
+/// user file, the nearest project `.stt/config.toml` and `STT_*` variables.
+fn settings_layers() -> Result<Vec<layers::Layer>> {
+    let defaults = settings_to_table(&AppSettings::default())?;
+    let mut settings_layers = vec![layers::Layer {
+        origin: layers::Origin::Default,
+        table: defaults.clone(),
+    }];
+    let project_config = std::env::current_dir()
+        .ok()
+        .and_then(|dir| layers::find_project_config(&dir));
+    let paths = [PathBuf::from(SYSTEM_CONFIG_PATH), get_app_settings_path()?]
+        .into_iter()
+        .chain(project_config.clone());
+    for path in paths {
+        if let Some(mut layer) = layers::Layer::from_file(path.clone())? {
+            // Only the user file is upgraded in place; shared files are just checked
+            schema::ensure_supported(&layer.table, &path, CONFIG_MIGRATIONS.len() as u32)?;
+            layer.table.remove(schema::VERSION_KEY);
+            if Some(&path) == project_config.as_ref() {
+                let ignored = layer.retain_keys(layers::PROJECT_KEYS);
+                if !ignored.is_empty() {
+                    eprintln!(
+                        "Warning: ignoring {} in {:?}; a project config may only set {}.",
+                        ignored.join(", "),
+                        path,
+                        layers::PROJECT_KEYS.join(", ")
+                    );
+                }
+            }
+            settings_layers.push(layer);
+        }
+    }
+    let keys: Vec<String> = SettingKey::value_variants()
+        .iter()
+        .map(|key| key.name())
+        .collect();
+    // Variables that are not valid Unicode can't hold a setting
+    let env = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    settings_layers.extend(layers::Layer::from_env(&keys, &defaults, env)?);
+    Ok(settings_layers)
 }
 
-fn write_app_settings(settings: &AppSettings) -> Result<()> {
-    let app_config_dir = get_app_config_dir()?;
-    let config_file_path = app_config_dir.join(DEFAULT_CONFIG_FILENAME);
-    let toml_content =
-        toml::to_string_pretty(settings).context("Failed to serialize app settings to TOML")?;
-    fs::write(&config_file_path, toml_content)
-        .with_context(|| format!("Failed to write app config to {:?}", config_file_path))?;
-    println!("Configuration saved to {:?}", config_file_path);
+/// Merges every settings layer, returning the settings and where each key came from.
+fn load_layered_settings() -> Result<(AppSettings, BTreeMap<String, layers::Origin>)> {
+    let settings_layers = settings_layers()?;
+    let (merged, origins) = layers::merge(&settings_layers);
+    match merged.try_into() {
+        Ok(settings) => Ok((settings, origins)),
+        Err(e) => {
+            // Point at the layer with the bad value rather than the merged result
+            let defaults = &settings_layers[0].table;
+            for layer in &settings_layers[1..] {
+                let mut table = defaults.clone();
+                table.extend(layer.table.clone());
+                if let Err(layer_error) = table.try_into::<AppSettings>() {
+                    anyhow::bail!(
+                        "Invalid settings in {}: {}",
+                        layer.origin,
+                        layer_error.message()
+                    );
+                }
+            }
+            anyhow::bail!("Invalid settings: {}", e.message())
+        }
+    }
+}
+
+fn get_app_settings_path() -> Result<PathBuf> {
+    Ok(get_app_config_dir()?.join(DEFAULT_CONFIG_FILENAME))
+}
+
+/// Checks the settings beyond what parsing already guarantees.
+fn validate_settings(settings: &AppSettings) -> Result<()> {
+    for (key, value) in [
+        ("profiles_filename", &settings.profiles_filename),
+        (
+            "cli_config_dir_from_home",
+            &settings.cli_config_dir_from_home,
+        ),
+        ("cli_config_filename", &settings.cli_config_filename),
+        ("contexts_filename", &settings.contexts_filename),
+        ("spacetime_command", &settings.spacetime_command),
+    ] {
+        if value.trim().is_empty() {
+            anyhow::bail!("{} must not be empty.", key);
+        }
+    }
+    token_key_path(settings)?;
+    discovery::expand_dir(&settings.cli_config_dir_from_home)
+        .context("Invalid cli_config_dir_from_home")?;
     Ok(())
 }
 
-fn get_profiles_filepath(settings: &AppSettings) -> Result<PathBuf> {
-    // Renamed function
-    let app_config_dir = get_app_config_dir()?;
-    Ok(app_config_dir.join(&settings.profiles_filename)) // Renamed field
+fn settings_to_table(settings: &AppSettings) -> Result<toml::Table> {
+    toml::Table::try_from(settings).context("Failed to serialize app settings")
 }
 
-fn get_cli_toml_path(settings: &AppSettings) -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().context("Failed to get home directory")?;
-    Ok(home_dir
-        .join(&settings.cli_config_dir_from_home)
-        .join(&settings.cli_config_filename))
+/// Returns `settings` with `key` replaced by `value`, checked against the
+/// `AppSettings` schema. `None` resets the key to its default.
+fn update_setting(
+    settings: &AppSettings,
+    key: SettingKey,
+    value: Option<&str>,
+) -> Result<AppSettings> {
+    let name = key.name();
+    let table = settings_to_table(settings)?;
+    let parse = |value: Option<toml::Value>| -> Result<AppSettings> {
+        let mut table = table.clone();
+        match value {
+            Some(value) => table.insert(name.clone(), value),
+            None => table.remove(&name),
+        };
+        table.try_into().map_err(|e: toml::de::Error| {
+            anyhow::anyhow!("Invalid value for {}: {}", name, e.message())
+        })
+    };
+
+    let updated = match value {
+        // Accept a TOML value (a number, a list, a quoted string), falling
+        // back to the raw text for the many plain string settings
+        Some(raw) => match format!("value = {}", raw)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+        {
+            Some(value) => parse(Some(value))
+                .or_else(|e| parse(Some(toml::Value::String(raw.to_string()))).map_err(|_| e))?,
+            None => parse(Some(toml::Value::String(raw.to_string())))?,
+        },
+        None => parse(settings_to_table(&AppSettings::default())?.remove(&name))?,
+    };
+    validate_settings(&updated)?;
+    Ok(updated)
 }
 
-fn read_profiles(settings: &AppSettings) -> Result<UserProfiles> {
-    // Renamed function and return type
-    let profiles_path = get_profiles_filepath(settings)?; // Renamed variable
-    if !profiles_path.exists() {
-        match fs::write(&profiles_path, "") {
-            // Renamed variable
-            Ok(_) => println!("Created empty {}.", settings.profiles_filename), // Renamed field
-            Err(e) => {
-                return Err(anyhow::Error::new(e).context(format!(
-                    "Failed to create empty profiles file at {:?}", // Renamed
-                    profiles_path                                   // Renamed variable
-                )));
+/// Sets each named key in the user config.toml, or removes it where the
+/// value is `None`, leaving the rest of the file as written.
+fn write_user_settings(changes: &[(String, Option<toml::Value>)]) -> Result<()> {
+    let config_file_path = get_app_settings_path()?;
+    let content = if config_file_path.exists() {
+        fs::read_to_string(&config_file_path)
+            .with_context(|| format!("Failed to read app config file at {:?}", config_file_path))?
+    } else {
+        String::new()
+    };
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse app config file at {:?}", config_file_path))?;
+    for (name, value) in changes {
+        match value {
+            Some(value) => {
+                let item = format!("{} = {}", name, value)
+                    .parse::<DocumentMut>()
+                    .ok()
+                    .and_then(|mut parsed| parsed.remove(name))
+                    .with_context(|| format!("Failed to serialize {}", name))?;
+                doc.insert(name, item);
+            }
+            None => {
+                doc.remove(name);
             }
         }
-        return Ok(UserProfiles::default()); // Renamed type
     }
+    if !doc.contains_key(schema::VERSION_KEY) {
+        let stamped = schema::stamp(&doc.to_string(), CONFIG_MIGRATIONS.len() as u32);
+        doc = stamped
+            .parse()
+            .with_context(|| format!("Failed to serialize {:?}", config_file_path))?;
+    }
+    fs::write(&config_file_path, doc.to_string())
+        .with_context(|| format!("Failed to write app config to {:?}", config_file_path))?;
+    println!("Configuration saved to {:?}", config_file_path);
+    Ok(())
+}
+
+/// Warns when a project config or `STT_*` variable hides the user setting `name`.
+fn warn_if_overridden(name: &str) -> Result<()> {
+    let user_config = layers::Origin::File(get_app_settings_path()?);
+    let (_, origins) = layers::merge(&settings_layers()?);
+    if let Some(origin) = origins.get(name).filter(|origin| **origin != user_config) {
+        println!(
+            "Warning: {} is overridden by {}, so the new value is not in effect here.",
+            name, origin
+        );
+    }
+    Ok(())
+}
+
+/// Runs a `config` subcommand. Invalid settings are reported but don't stop
+/// it: `get` and `list` fall back to the raw merged values, and `set` and
+/// `unset` edit the user file regardless, which is how such settings get fixed.
+fn run_config_command(command: ConfigCommands) -> Result<()> {
+    prepare_user_config()?;
+    let loaded = load_layered_settings();
+    let (table, origins) = match &loaded {
+        Ok((settings, origins)) => (settings_to_table(settings)?, origins.clone()),
+        Err(e) => {
+            if matches!(command, ConfigCommands::Get(_) | ConfigCommands::List(_)) {
+                eprintln!("Warning: {:#}", e);
+            }
+            layers::merge(&settings_layers()?)
+        }
+    };
+    let warn_if_invalid = || {
+        if let Err(e) = load_layered_settings() {
+            eprintln!("Warning: the settings are still invalid: {:#}", e);
+        }
+    };
+
+    match command {
+        ConfigCommands::Get(args) => match table.get(&args.key.name()) {
+            Some(toml::Value::String(value)) => println!("{}", value),
+            Some(value) => println!("{}", value),
+            // Only empty lists are left out when serializing
+            None => println!("[]"),
+        },
+        ConfigCommands::Set(args) => {
+            let name = args.key.name();
+            // With invalid settings, check the new value against the defaults instead
+            let base = loaded.map(|(settings, _)| settings).unwrap_or_default();
+            let updated = update_setting(&base, args.key, Some(&args.value))?;
+            let value = settings_to_table(&updated)?
+                .remove(&name)
+                .unwrap_or_else(|| toml::Value::Array(Vec::new()));
+            write_user_settings(&[(name.clone(), Some(value))])?;
+            warn_if_overridden(&name)?;
+            warn_if_invalid();
+        }
+        ConfigCommands::Unset(args) => {
+            let name = args.key.name();
+            write_user_settings(&[(name.clone(), None)])?;
+            let (_, origins) = layers::merge(&settings_layers()?);
+            if let Some(origin) = origins.get(&name) {
+                if *origin != layers::Origin::Default {
+                    println!("{} now comes from {}.", name, origin);
+                }
+            }
+            warn_if_invalid();
+        }
+        ConfigCommands::List(args) => {
+            if args.show_origin {
+                for key in SettingKey::value_variants() {
+                    let name = key.name();
+                    let value = table
+                        .get(&name)
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| "[]".to_string());
+                    let origin = origins.get(&name).unwrap_or(&layers::Origin::Default);
+                    println!("{}\t{} = {}", origin, name, value);
+                }
+            } else {
+                let content = match &loaded {
+                    Ok((settings, _)) => toml::to_string_pretty(settings),
+                    Err(_) => toml::to_string_pretty(&table),
+                };
+                print!("{}", content.context("Failed to serialize app settings")?);
+            }
+        }
+        ConfigCommands::Path => println!("{}", get_app_settings_path()?.display()),
+    }
+    Ok(())
+}
+
+fn get_profiles_filepath(settings: &AppSettings) -> Result<PathBuf> {
+    // Renamed function
+    let app_config_dir = get_app_config_dir()?;
+    Ok(app_config_dir.join(&settings.profiles_filename)) // Renamed field
+}
+
+fn get_cli_toml_path(settings: &AppSettings) -> Result<PathBuf> {
+    let cli_config_dir = discovery::expand_dir(&settings.cli_config_dir_from_home)
+        .context("Invalid cli_config_dir_from_home")?;
+    Ok(cli_config_dir.join(&settings.cli_config_filename))
+}
+
+fn read_profiles(settings: &AppSettings) -> Result<UserProfiles> {
+    // Renamed function and return type
+    let profiles_path = get_profiles_filepath(settings)?; // Renamed variable
+    if !profiles_path.exists() {
+        match fs::write(&profiles_path, "") {
+            // Renamed variable
+            Ok(_) => eprintln!("Created empty {}.", settings.profiles_filename), // Renamed field
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!(
+                    "Failed to create empty profiles file at {:?}", // Renamed
+                    profiles_path                                   // Renamed variable
+                )));
+            }
+        }
+        return Ok(UserProfiles::default()); // Renamed type
+    }
+    schema::upgrade(&profiles_path, PROFILES_MIGRATIONS, &get_backups_dir()?)?;
     let content = fs::read_to_string(&profiles_path) // Renamed variable
         .with_context(|| format!("Failed to read profiles file at {:?}", profiles_path))?; // Renamed
     if content.trim().is_empty() {
         return Ok(UserProfiles::default()); // Renamed type
     }
-    toml::from_str(&content).with_context(|| {
+    let parse_error = || {
         format!(
             "Failed to parse profiles file at {:?}. Ensure it's valid TOML or empty.", // Renamed
             profiles_path // Renamed variable
         )
-    })
+    };
+    let mut table: toml::Table = toml::from_str(&content).with_context(parse_error)?;
+    table.remove(schema::VERSION_KEY);
+    table.try_into().with_context(parse_error)
 }
 
 fn write_profiles(settings: &AppSettings, profiles: &UserProfiles) -> Result<()> {
     // Renamed function and param
     let profiles_path = get_profiles_filepath(settings)?; // Renamed variable
-    let content =
-        toml::to_string_pretty(profiles).context("Failed to serialize profiles data to TOML")?; // Renamed
+    let content = serialize_profiles(profiles, &settings.profiles_filename)?;
     fs::write(&profiles_path, content) // Renamed variable
         .with_context(|| format!("Failed to write profiles file at {:?}", profiles_path))?; // Renamed
     println!("Successfully updated {}.", settings.profiles_filename); // Renamed field
     Ok(())
 }
 
+/// Renders `profiles` as a stamped profiles file.
+fn serialize_profiles(profiles: &UserProfiles, profiles_filename: &str) -> Result<String> {
+    if profiles.0.contains_key(schema::VERSION_KEY) {
+        anyhow::bail!(
+            "'{}' is reserved in {} and cannot be used as a profile name.",
+            schema::VERSION_KEY,
+            profiles_filename
+        );
+    }
+    let content =
+        toml::to_string_pretty(profiles).context("Failed to serialize profiles data to TOML")?; // Renamed
+    Ok(schema::stamp(&content, PROFILES_MIGRATIONS.len() as u32))
+}
+
+fn get_backups_dir() -> Result<PathBuf> {
+    Ok(get_app_config_dir()?.join(BACKUPS_DIR_NAME))
+}
+
+fn get_contexts_filepath(settings: &AppSettings) -> Result<PathBuf> {
+    Ok(get_app_config_dir()?.join(&settings.contexts_filename))
+}
+
+fn read_contexts(settings: &AppSettings) -> Result<Contexts> {
+    let contexts_path = get_contexts_filepath(settings)?;
+    if !contexts_path.exists() {
+        return Ok(Contexts::default());
+    }
+    let content = fs::read_to_string(&contexts_path)
+        .with_context(|| format!("Failed to read contexts file at {:?}", contexts_path))?;
+    toml::from_str(&content).with_context(|| {
+        format!(
+            "Failed to parse contexts file at {:?}. Ensure it's valid TOML or empty.",
+            contexts_path
+        )
+    })
+}
+
+fn write_contexts(settings: &AppSettings, contexts: &Contexts) -> Result<()> {
+    let contexts_path = get_contexts_filepath(settings)?;
+    let content =
+        toml::to_string_pretty(contexts).context("Failed to serialize contexts data to TOML")?;
+    fs::write(&contexts_path, content)
+        .with_context(|| format!("Failed to write contexts file at {:?}", contexts_path))?;
+    println!("Successfully updated {}.", settings.contexts_filename);
+    Ok(())
+}
+
 fn read_cli_toml(settings: &AppSettings) -> Result<DocumentMut> {
     let path = get_cli_toml_path(settings)?;
     let content = fs::read_to_string(&path).with_context(|| {
@@ -215,6 +1371,19 @@ fn read_cli_toml(settings: &AppSettings) -> Result<DocumentMut> {
     })
 }
 
+/// Reads cli.toml, or starts an empty document (creating its directory) if it does not exist yet.
+fn read_or_create_cli_toml(settings: &AppSettings) -> Result<DocumentMut> {
+    let cli_toml_path = get_cli_toml_path(settings)?;
+    if cli_toml_path.exists() {
+        return read_cli_toml(settings);
+    }
+    if let Some(parent_dir) = cli_toml_path.parent() {
+        fs::create_dir_all(parent_dir)
+            .with_context(|| format!("Failed to create directory {:?}", parent_dir))?;
+    }
+    Ok(DocumentMut::new())
+}
+
 fn write_cli_toml(settings: &AppSettings, doc: &DocumentMut) -> Result<()> {
     let path = get_cli_toml_path(settings)?;
     fs::write(&path, doc.to_string()).with_context(|| {
@@ -227,9 +1396,222 @@ fn write_cli_toml(settings: &AppSettings, doc: &DocumentMut) -> Result<()> {
     Ok(())
 }
 
-fn run_external_command(command_name: &str, args: &[&str]) -> Result<()> {
+/// The servers cli.toml knows about as `(nickname, base URL)` pairs: its
+/// `server_configs` followed by any built-in servers they don't override.
+fn known_servers(cli_toml: &DocumentMut) -> Vec<(String, String)> {
+    let mut servers: Vec<(String, String)> = cli_toml
+        .get("server_configs")
+        .and_then(|item| item.as_array_of_tables())
+        .map(|configs| {
+            configs
+                .iter()
+                .filter_map(|config| {
+                    let nickname = config.get("nickname")?.as_str()?;
+                    let host = config.get("host")?.as_str()?;
+                    let protocol = config
+                        .get("protocol")
+                        .and_then(|protocol| protocol.as_str())
+                        .unwrap_or("https");
+                    Some((nickname.to_string(), format!("{}://{}", protocol, host)))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    for (nickname, url) in BUILTIN_SERVERS {
+        if !servers.iter().any(|(configured, _)| configured == nickname) {
+            servers.push((nickname.to_string(), url.to_string()));
+        }
+    }
+    servers
+}
+
+/// Resolves a server URL or cli.toml nickname to the server's base URL.
+/// Without a server, cli.toml's `default_server` (or `local`) is used.
+fn resolve_server_url(settings: &AppSettings, server: Option<&str>) -> Result<String> {
+    if let Some(url) = server.filter(|server| server.contains("://")) {
+        return Ok(url.trim_end_matches('/').to_string());
+    }
+
+    let cli_toml_path = get_cli_toml_path(settings)?;
+    let cli_toml = if cli_toml_path.exists() {
+        read_cli_toml(settings)?
+    } else {
+        DocumentMut::new()
+    };
+    let nickname = match server {
+        Some(nickname) => nickname.to_string(),
+        None => cli_toml
+            .get("default_server")
+            .and_then(|item| item.as_str())
+            .unwrap_or(DEFAULT_LOGIN_SERVER)
+            .to_string(),
+    };
+
+    known_servers(&cli_toml)
+        .into_iter()
+        .find(|(known, _)| *known == nickname)
+        .map(|(_, url)| url)
+        .with_context(|| {
+            format!(
+                "Unknown server '{}'. Use a URL or a nickname from {}.",
+                nickname, settings.cli_config_filename
+            )
+        })
+}
+
+/// The lowercase host name of a URL or bare host, without scheme, port or path.
+fn url_host(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = without_scheme.split('/').next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.to_lowercase()
+}
+
+fn is_local_host(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0")
+}
+
+/// Checks that a token's issuer plausibly belongs to the server it is about to
+/// be used against: `server` where activation makes it the default, otherwise
+/// cli.toml's `default_server`. A profile's bound server does not count, since
+/// `switch`, `set` and `admin` leave `default_server` as it is. Tokens from a
+/// local issuer are refused for remote servers, and tokens issued by one known
+/// server are refused for another, unless `allow_mismatch` is set.
+fn check_issuer_matches(
+    settings: &AppSettings,
+    token: &str,
+    server: Option<&str>,
+    allow_mismatch: bool,
+) -> Result<()> {
+    // Tokens that can't be decoded are not ours to judge
+    let Some(issuer) = token::decode_claims(token)
+        .ok()
+        .and_then(|claims| claims.iss)
+    else {
+        return Ok(());
+    };
+    let server_url = match resolve_server_url(settings, server) {
+        Ok(server_url) => server_url,
+        // A removed or misspelled server leaves nothing to compare against;
+        // that must not block activation, with or without --allow-mismatch
+        Err(e) => {
+            println!("Warning: skipping the issuer check: {:#}", e);
+            return Ok(());
+        }
+    };
+    let issuer_host = url_host(&issuer);
+    let server_host = url_host(&server_url);
+    if issuer_host == server_host || (is_local_host(&issuer_host) && is_local_host(&server_host)) {
+        return Ok(());
+    }
+
+    let reason = if is_local_host(&issuer_host) {
+        Some(format!(
+            "the token was issued locally ('{}') but the server is {}",
+            issuer, server_url
+        ))
+    } else {
+        let cli_toml_path = get_cli_toml_path(settings)?;
+        let cli_toml = if cli_toml_path.exists() {
+            read_cli_toml(settings)?
+        } else {
+            DocumentMut::new()
+        };
+        known_servers(&cli_toml)
+            .into_iter()
+            .find(|(_, url)| url_host(url) == issuer_host)
+            .map(|(nickname, _)| {
+                format!(
+                    "the token was issued by server '{}' ('{}') but the server is {}",
+                    nickname, issuer, server_url
+                )
+            })
+    };
+
+    match reason {
+        None => Ok(()),
+        Some(reason) if allow_mismatch => {
+            println!("Warning: {}.", reason);
+            Ok(())
+        }
+        Some(reason) => anyhow::bail!(
+            "Issuer mismatch: {}. Use --allow-mismatch to activate it anyway.",
+            reason
+        ),
+    }
+}
+
+/// Exchanges `profile`'s token for a websocket token on `server_url` and
+/// formats it the way `ws-token` prints it.
+fn ws_token_output(
+    profile_name: &str,
+    profile: &Profile,
+    server_url: &str,
+    json: bool,
+) -> Result<String> {
+    let websocket_token = api::ApiClient::new(server_url).websocket_token(&profile.token)?;
+    if !json {
+        return Ok(websocket_token);
+    }
+    let output = WsTokenOutput {
+        profile: profile_name,
+        server: server_url,
+        token: &websocket_token,
+    };
+    serde_json::to_string(&output).context("Failed to serialize output to JSON")
+}
+
+/// Asks the server whether `profile`'s token is valid for `identity`, and
+/// records the time in `last_verified` when it is.
+fn verify_profile(
+    client: &api::ApiClient,
+    identity: &str,
+    profile: &mut Profile,
+) -> Result<api::Verification> {
+    let verification = client.verify_token(identity, &profile.token)?;
+    if verification == api::Verification::Valid {
+        profile.last_verified = Some(token::now_unix());
+    }
+    Ok(verification)
+}
+
+/// Resolves the profile's `spacetime_command` override, or else the global
+/// `spacetime_command`, to the real SpacetimeDB CLI. Bare names are looked up
+/// in PATH, skipping this executable so an installed shim never ends up
+/// calling itself.
+fn spacetime_binary(settings: &AppSettings, profile: Option<&Profile>) -> Result<PathBuf> {
+    let command = profile
+        .and_then(|profile| profile.spacetime_command.as_ref())
+        .unwrap_or(&settings.spacetime_command);
+    if command.contains(std::path::MAIN_SEPARATOR) || command.contains('/') {
+        return match command.strip_prefix("~/") {
+            Some(rest) => Ok(dirs::home_dir()
+                .context("Failed to get home directory")?
+                .join(rest)),
+            None => Ok(PathBuf::from(command)),
+        };
+    }
+
+    let own_exe = std::env::current_exe().and_then(fs::canonicalize).ok();
+    let path_var = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(format!("{}{}", command, std::env::consts::EXE_SUFFIX)))
+        .find(|candidate| candidate.is_file() && fs::canonicalize(candidate).ok() != own_exe)
+        .with_context(|| {
+            format!(
+                "Could not find '{}' in your PATH. Set spacetime_command in {} (or `binary bind` a profile) to the path of the SpacetimeDB CLI.",
+                command, DEFAULT_CONFIG_FILENAME
+            )
+        })
+}
+
+fn run_external_command(program: &Path, args: &[&str]) -> Result<()> {
+    let command_name = program.display();
     println!("Running: {} {}...", command_name, args.join(" "));
-    let mut cmd = StdCommand::new(command_name);
+    let mut cmd = StdCommand::new(program);
     cmd.args(args);
 
     let status = cmd
@@ -237,12 +1619,7 @@ fn run_external_command(command_name: &str, args: &[&str]) -> Result<()> {
         .stdout(std::process::Stdio::inherit())
         .stderr(std::process::Stdio::inherit())
         .status()
-        .with_context(|| {
-            format!(
-                "Failed to execute command: {}. Is '{}' in your PATH?",
-                command_name, command_name
-            )
-        })?;
+        .with_context(|| format!("Failed to execute command: {}", command_name))?;
 
     if status.success() {
         println!(
@@ -261,413 +1638,2802 @@ fn run_external_command(command_name: &str, args: &[&str]) -> Result<()> {
     }
 }
 
-fn mask_token(token: &str) -> String {
-    if token.len() <= 10 {
-        // Arbitrary length, too short to mask meaningfully
-        return token.to_string();
+/// Runs the login flow with the profile's SpacetimeDB CLI (the global one for new profiles).
+fn run_login_flow(
+    settings: &AppSettings,
+    profile: Option<&Profile>,
+    flow: &LoginFlow,
+) -> Result<()> {
+    let spacetime = spacetime_binary(settings, profile)?;
+    // A token key that doesn't fit this CLI version means the new token can't be found afterwards
+    if let Ok(version) = cli_version::CliVersion::detect(&spacetime) {
+        if let Some(warning) = version.layout_warning(&settings.cli_token_key) {
+            println!("Warning: {}", warning);
+        }
     }
-    format!("{}...{}", &token[..5], &token[token.len() - 5..])
+    if !flow.no_logout {
+        run_external_command(&spacetime, &["logout"])
+            .context("Failed to logout from SpacetimeDB CLI.")?;
+    }
+
+    let login_args = flow.login_args();
+    let login_args: Vec<&str> = login_args.iter().map(String::as_str).collect();
+    let login_display = format!("{} {}", SPACETIME_CLI_COMMAND, login_args.join(" "));
+    println!("Please follow the prompts from '{}'.", login_display);
+    run_external_command(&spacetime, &login_args)
+        .with_context(|| format!("Failed during '{}'.", login_display))
 }
 
-fn main() -> Result<()> {
-    let settings = load_app_settings().context("Failed to load application settings")?;
-    let cli = Cli::parse();
+/// Parses `cli_token_key`, which may be a plain key or a dotted key path.
+fn token_key_path(settings: &AppSettings) -> Result<key_path::KeyPath> {
+    key_path::KeyPath::parse(&settings.cli_token_key)
+        .with_context(|| format!("Invalid cli_token_key '{}'", settings.cli_token_key))
+}
 
-    match cli.command {
-        Commands::Set(args) => {
-            let mut profiles = read_profiles(&settings)?; // Renamed
-            profiles // Renamed
-                .0
-                .insert(args.profile_name.clone(), args.token.clone()); // Renamed
-            write_profiles(&settings, &profiles)?; // Renamed
-            println!(
-                "Profile '{}' saved/updated in {}.", // Renamed
-                args.profile_name,
-                settings.profiles_filename // Renamed
-            );
+fn get_cli_token<'a>(
+    settings: &AppSettings,
+    cli_toml: &'a DocumentMut,
+) -> Result<Option<&'a Item>> {
+    Ok(token_key_path(settings)?.get(cli_toml))
+}
 
-            let cli_toml_path = get_cli_toml_path(&settings)?;
-            let mut cli_toml = if cli_toml_path.exists() {
-                read_cli_toml(&settings)?
-            } else {
-                if let Some(parent_dir) = cli_toml_path.parent() {
-                    fs::create_dir_all(parent_dir)
-                        .with_context(|| format!("Failed to create directory {:?}", parent_dir))?;
-                }
-                DocumentMut::new()
-            };
-            cli_toml[&settings.cli_token_key] = Item::Value(args.token.into());
-            write_cli_toml(&settings, &cli_toml)?;
-            println!(
-                "Profile '{}' also set as active token in {}.", // Renamed
-                args.profile_name,
-                settings.cli_config_filename // Renamed
-            );
-        }
-        Commands::Switch(args) => {
-            let profiles = read_profiles(&settings)?; // Renamed
-            let profile_name_to_switch = match args.profile_name {
-                // Renamed
-                Some(name) => name,
-                None => {
-                    if profiles.0.is_empty() {
-                        // Renamed
-                        println!(
-                            "No profiles found in {}. Cannot switch.", // Renamed
-                            settings.profiles_filename                 // Renamed
-                        );
-                        anyhow::bail!("No profiles available to switch."); // Renamed
-                    }
-                    let profile_names: Vec<&String> = profiles.0.keys().collect(); // Renamed
-                    let selection = Select::with_theme(&ColorfulTheme::default())
-                        .with_prompt("Select profile to switch to") // Renamed
-                        .items(&profile_names) // Renamed
-                        .default(0)
-                        .interact_opt()?
-                        .context("No profile selected or selection cancelled.")?; // Renamed
+fn set_cli_token(settings: &AppSettings, cli_toml: &mut DocumentMut, token: &str) -> Result<()> {
+    token_key_path(settings)?
+        .set(cli_toml, token)
+        .with_context(|| {
+            format!(
+                "Failed to set '{}' in {}",
+                settings.cli_token_key, settings.cli_config_filename
+            )
+        })
+}
 
-                    profile_names[selection].clone() // Renamed
-                }
-            };
+/// Reads the token currently stored in cli.toml, failing if it is missing.
+fn read_active_token(settings: &AppSettings) -> Result<String> {
+    let cli_toml_path = get_cli_toml_path(settings)?;
+    if !cli_toml_path.exists() {
+        anyhow::bail!("{} does not exist.", settings.cli_config_filename);
+    }
+    let cli_toml = read_cli_toml(settings)?;
+    match get_cli_token(settings, &cli_toml)? {
+        Some(token_item) => match token_item.as_str() {
+            Some(token_str) => Ok(token_str.to_string()),
+            None => anyhow::bail!(
+                "Token key '{}' in {} is not a string.",
+                settings.cli_token_key,
+                settings.cli_config_filename
+            ),
+        },
+        None => anyhow::bail!(
+            "Token key '{}' not found in {}.",
+            settings.cli_token_key,
+            settings.cli_config_filename
+        ),
+    }
+}
 
-            if let Some(token_from_profiles) = profiles.0.get(&profile_name_to_switch) {
-                // Renamed
-                let cli_toml_path = get_cli_toml_path(&settings)?;
-                let mut cli_toml = if cli_toml_path.exists() {
-                    read_cli_toml(&settings)?
-                } else {
-                    if let Some(parent_dir) = cli_toml_path.parent() {
-                        fs::create_dir_all(parent_dir).with_context(|| {
-                            format!("Failed to create directory {:?}", parent_dir)
-                        })?;
-                    }
-                    DocumentMut::new()
-                };
-                cli_toml[&settings.cli_token_key] = Item::Value(token_from_profiles.clone().into()); // Renamed
-                write_cli_toml(&settings, &cli_toml)?;
-                println!(
-                    "Switched active token to profile '{}' (from {}) in {}.", // Renamed
-                    profile_name_to_switch,                                   // Renamed
-                    settings.profiles_filename,                               // Renamed
-                    settings.cli_config_filename
-                );
-            } else {
-                println!(
-                    "Profile '{}' not found in {}. Cannot switch.", // Renamed
-                    profile_name_to_switch,
-                    settings.profiles_filename // Renamed
-                );
-                println!("Available profiles: {:?}", profiles.0.keys()); // Renamed
-                anyhow::bail!("Profile not found in profiles file for switching.");
-                // Renamed
-            }
+/// Sets the active token in cli.toml, or removes it when `token` is `None`.
+fn write_active_token(settings: &AppSettings, token: Option<&str>) -> Result<()> {
+    let mut cli_toml = read_or_create_cli_toml(settings)?;
+    match token {
+        Some(token) => set_cli_token(settings, &mut cli_toml, token)?,
+        None => {
+            token_key_path(settings)?.remove(&mut cli_toml);
         }
-        Commands::Admin => {
-            let admin_profile_name = "admin".to_string(); // Renamed
-            let profiles = read_profiles(&settings)?; // Renamed
-            if let Some(token_from_profiles) = profiles.0.get(&admin_profile_name) {
-                // Renamed
-                let cli_toml_path = get_cli_toml_path(&settings)?;
-                let mut cli_toml = if cli_toml_path.exists() {
-                    read_cli_toml(&settings)?
-                } else {
-                    if let Some(parent_dir) = cli_toml_path.parent() {
-                        fs::create_dir_all(parent_dir).with_context(|| {
-                            format!("Failed to create directory {:?}", parent_dir)
-                        })?;
-                    }
-                    DocumentMut::new()
-                };
-                cli_toml[&settings.cli_token_key] = Item::Value(token_from_profiles.clone().into()); // Renamed
-                write_cli_toml(&settings, &cli_toml)?;
-                println!(
-                    "Switched active token to ADMIN profile '{}' (from {}) in {}.", // Renamed
-                    admin_profile_name,
-                    settings.profiles_filename,
-                    settings.cli_config_filename // Renamed
-                );
-            } else {
-                println!(
-                    "ADMIN profile ('{}') not found in {}. Cannot switch.", // Renamed
-                    admin_profile_name,
-                    settings.profiles_filename // Renamed
-                );
-                println!("Ensure a profile named 'admin' exists with a valid token."); // Renamed
-                anyhow::bail!("Admin profile not found."); // Renamed
-            }
+    }
+    write_cli_toml(settings, &cli_toml)?;
+    if let Some(token) = token {
+        update_targets(settings, token);
+    }
+    Ok(())
+}
+
+/// Writes the active token to every extra target. A target that cannot be
+/// updated only produces a warning, since cli.toml has already been written.
+fn update_targets(settings: &AppSettings, token: &str) {
+    for target in &settings.targets {
+        match target.write_token(token) {
+            Ok(()) => println!("Updated '{}' in {}.", target.key, target.path),
+            Err(e) => println!("Warning: {:#}", e),
         }
-        Commands::Save(args) => {
-            let cli_toml_path = get_cli_toml_path(&settings)?;
+    }
+}
+
+/// A private copy of cli.toml holding one profile's token, so a spacetime
+/// process can run as that profile (`spacetime --config-path <path>`) without
+/// touching the global cli.toml. The copy is deleted when this is dropped.
+struct IsolatedConfig {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl IsolatedConfig {
+    fn create(settings: &AppSettings, profile: &Profile) -> Result<Self> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        // Unpredictable, and created exclusively: a directory planted at this
+        // path by someone else makes this fail instead of receiving the token
+        let dir = std::env::temp_dir().join(format!(
+            "stt-{}-{}-{:016x}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            rand_core::RngCore::next_u64(&mut rand_core::OsRng)
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder
+            .create(&dir)
+            .with_context(|| format!("Failed to create directory {:?}", dir))?;
+        // Construct before writing so the directory is cleaned up on failure
+        let config = Self {
+            path: dir.join(&settings.cli_config_filename),
+            dir,
+        };
+
+        let cli_toml_path = get_cli_toml_path(settings)?;
+        let mut cli_toml = if cli_toml_path.exists() {
+            read_cli_toml(settings)?
+        } else {
+            DocumentMut::new()
+        };
+        set_cli_token(settings, &mut cli_toml, &profile.token)?;
+        if let Some(server) = profile.server.as_deref().filter(|s| !s.contains("://")) {
+            cli_toml["default_server"] = Item::Value(server.into());
+        }
+        fs::write(&config.path, cli_toml.to_string())
+            .with_context(|| format!("Failed to write {:?}", config.path))?;
+        Ok(config)
+    }
+}
+
+impl Drop for IsolatedConfig {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The result of running spacetime as one profile in `foreach`
+struct ForeachOutcome {
+    status: Result<std::process::ExitStatus>,
+    stdout: String,
+    stderr: String,
+}
+
+fn run_as_profile(
+    settings: &AppSettings,
+    profile: &Profile,
+    spacetime_args: &[String],
+) -> ForeachOutcome {
+    let output = IsolatedConfig::create(settings, profile).and_then(|config| {
+        let spacetime = spacetime_binary(settings, Some(profile))?;
+        StdCommand::new(&spacetime)
+            .arg("--config-path")
+            .arg(&config.path)
+            .args(spacetime_args)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to execute command: {}", spacetime.display()))
+    });
+    match output {
+        Ok(output) => ForeachOutcome {
+            status: Ok(output.status),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        },
+        Err(e) => ForeachOutcome {
+            status: Err(e),
+            stdout: String::new(),
+            stderr: String::new(),
+        },
+    }
+}
+
+/// Why a profile was selected for this shell or directory
+enum ProfileSource {
+    Env,
+    Pin(PathBuf),
+}
+
+impl std::fmt::Display for ProfileSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileSource::Env => write!(f, "${}", PROFILE_ENV_VAR),
+            ProfileSource::Pin(path) => write!(f, "pin {:?}", path),
+        }
+    }
+}
+
+/// The profile selected by `STT_PROFILE` or, failing that, the nearest
+/// directory pin. `None` means the global active token applies.
+fn selected_profile() -> Option<(String, ProfileSource)> {
+    if let Some(name) = std::env::var(PROFILE_ENV_VAR)
+        .ok()
+        .filter(|name| !name.is_empty())
+    {
+        return Some((name, ProfileSource::Env));
+    }
+    let cwd = std::env::current_dir().ok()?;
+    pin::find(&cwd).map(|pin| (pin.profile_name, ProfileSource::Pin(pin.path)))
+}
+
+/// Looks up the profile selected by `STT_PROFILE` or a pin, if any.
+fn load_selected_profile(settings: &AppSettings) -> Result<Option<(String, Profile)>> {
+    let Some((profile_name, source)) = selected_profile() else {
+        return Ok(None);
+    };
+    let mut profiles = read_profiles(settings)?;
+    let profile = profiles.0.remove(&profile_name).with_context(|| {
+        format!(
+            "Profile '{}' (selected by {}) not found in {}.",
+            profile_name, source, settings.profiles_filename
+        )
+    })?;
+    Ok(Some((profile_name, profile)))
+}
+
+/// spacetime subcommands that change cli.toml. Changes made to an isolated
+/// copy would be lost, so these run against the global cli.toml.
+const CONFIG_SUBCOMMANDS: &[&str] = &["server", "login", "logout"];
+
+/// The spacetime subcommand in `args`, skipping global options.
+fn spacetime_subcommand<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Option<String> {
+    let mut args = args
+        .iter()
+        .map(|arg| arg.as_ref().to_string_lossy().into_owned());
+    while let Some(arg) = args.next() {
+        if arg == "--root-dir" || arg == "--config-path" {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Ignores Ctrl+C (SIGINT and SIGQUIT) in this process while a child runs,
+/// like `system(3)` does: the child decides how to stop, and this process
+/// lives on to clean up after it. The previous handlers return on drop.
+struct LeaveInterruptsToChild {
+    #[cfg(unix)]
+    previous: Vec<(libc::c_int, libc::sighandler_t)>,
+}
+
+impl LeaveInterruptsToChild {
+    /// Call after spawning: ignored signals stay ignored in children.
+    fn start() -> Self {
+        Self {
+            #[cfg(unix)]
+            previous: [libc::SIGINT, libc::SIGQUIT]
+                .into_iter()
+                // SAFETY: installs the predefined SIG_IGN disposition, no handler code
+                .map(|signal| (signal, unsafe { libc::signal(signal, libc::SIG_IGN) }))
+                .collect(),
+        }
+    }
+}
+
+impl Drop for LeaveInterruptsToChild {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for &(signal, handler) in &self.previous {
+            // SAFETY: restores the disposition `signal` returned earlier
+            unsafe { libc::signal(signal, handler) };
+        }
+    }
+}
+
+/// Runs the real SpacetimeDB CLI with `args`, as the selected profile (through
+/// an isolated config) if there is one and `use_selected` is set. Returns the
+/// CLI's exit code.
+fn run_spacetime<S: AsRef<std::ffi::OsStr>>(
+    settings: &AppSettings,
+    args: &[S],
+    use_selected: bool,
+) -> Result<i32> {
+    let selected = match use_selected {
+        true => load_selected_profile(settings)?,
+        false => None,
+    };
+    let profile = selected.as_ref().map(|(_, profile)| profile);
+    let spacetime = spacetime_binary(settings, profile)?;
+    let mut command = StdCommand::new(&spacetime);
+    // Deleted on drop, so keep it until the command has finished
+    let mut isolated_config = None;
+    if let Some((profile_name, profile)) = &selected {
+        match spacetime_subcommand(args).filter(|sub| CONFIG_SUBCOMMANDS.contains(&sub.as_str())) {
+            Some(subcommand) => eprintln!(
+                "'spacetime {}' changes {}, so it runs against the global one, not as profile '{}'.",
+                subcommand, settings.cli_config_filename, profile_name
+            ),
+            None => {
+                let config = IsolatedConfig::create(settings, profile)?;
+                command.arg("--config-path").arg(&config.path);
+                isolated_config = Some(config);
+            }
+        }
+    }
+    let mut child = command
+        .args(args)
+        .spawn()
+        .with_context(|| format!("Failed to execute command: {}", spacetime.display()))?;
+    let interrupts = LeaveInterruptsToChild::start();
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for command: {}", spacetime.display()))?;
+    // Remove the token copy before Ctrl+C can end this process again
+    drop(isolated_config);
+    drop(interrupts);
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Whether this executable was started under the name `spacetime`, i.e. through the shim link.
+fn invoked_as_shim() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|path| path.file_stem().map(|stem| stem == SPACETIME_CLI_COMMAND))
+        .unwrap_or(false)
+}
+
+/// Runs the real SpacetimeDB CLI with this process's arguments, as the
+/// selected profile if there is one. Returns the CLI's exit code.
+fn run_shim(settings: &AppSettings) -> Result<i32> {
+    let args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+    // An explicit --config-path wins over the selected profile
+    let has_config_path = args.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == "--config-path" || arg.starts_with("--config-path=")
+    });
+    run_spacetime(settings, &args, !has_config_path)
+}
+
+fn find_profile_by_token<'a>(profiles: &'a UserProfiles, token: &str) -> Option<&'a String> {
+    profiles
+        .0
+        .iter()
+        .find(|(_, profile)| profile.token == token)
+        .map(|(name, _)| name)
+}
+
+fn default_issuer_key_path() -> Result<PathBuf> {
+    Ok(get_app_config_dir()?
+        .join(ISSUER_DIR_NAME)
+        .join(ISSUER_KEY_FILENAME))
+}
+
+/// Loads the issuer key from the config directory, generating it on first use.
+fn load_or_create_issuer_key() -> Result<keys::IssuerKey> {
+    let path = default_issuer_key_path()?;
+    if path.exists() {
+        return keys::IssuerKey::load(&path);
+    }
+    let key = keys::IssuerKey::generate();
+    key.save(&path)?;
+    eprintln!("Created issuer key {} at {:?}.", key.key_id(), path);
+    Ok(key)
+}
+
+/// Resolves an optional profile name argument, defaulting to the profile whose
+/// token is active in cli.toml.
+fn resolve_profile_name(
+    settings: &AppSettings,
+    profiles: &UserProfiles,
+    profile_name: Option<String>,
+) -> Result<String> {
+    let profile_name = match profile_name {
+        Some(name) => name,
+        None => {
+            let active_token = read_active_token(settings).context("No profile given.")?;
+            find_profile_by_token(profiles, &active_token)
+                .cloned()
+                .with_context(|| {
+                    format!(
+                        "No profile given and the active token is not stored in {}.",
+                        settings.profiles_filename
+                    )
+                })?
+        }
+    };
+    if !profiles.0.contains_key(&profile_name) {
+        anyhow::bail!(
+            "Profile '{}' not found in {}.",
+            profile_name,
+            settings.profiles_filename
+        );
+    }
+    Ok(profile_name)
+}
+
+/// Asks the profile's server which databases its identity owns.
+fn fetch_owned_databases(settings: &AppSettings, profile: &Profile) -> Result<Vec<OwnedDatabase>> {
+    let identity = token::decode_claims(&profile.token)?
+        .identity()
+        .context("Token has no identity claim.")?;
+    let server_url = resolve_server_url(settings, profile.server.as_deref())?;
+    let client = api::ApiClient::new(&server_url);
+    client
+        .owned_databases(&identity, &profile.token)?
+        .into_iter()
+        .map(|database_identity| {
+            let names = client.database_names(&database_identity)?;
+            Ok(OwnedDatabase {
+                identity: database_identity,
+                names,
+            })
+        })
+        .collect()
+}
+
+/// Before profiles are removed, checks whether their identities still own
+/// databases. If any do, or ownership can't be determined, offers an
+/// encrypted backup of their tokens and asks for confirmation. Returns
+/// whether the removal should go ahead.
+fn confirm_profile_removal(
+    settings: &AppSettings,
+    profiles: &UserProfiles,
+    profile_names: &[String],
+    removal: &RemovalArgs,
+) -> Result<bool> {
+    let mut owners = Vec::new();
+    for name in profile_names {
+        let profile = &profiles.0[name];
+        let databases = match &profile.databases {
+            Some(cached) if !removal.online => Some(cached.clone()),
+            _ => match fetch_owned_databases(settings, profile) {
+                Ok(databases) => Some(databases),
+                Err(e) => {
+                    println!(
+                        "Warning: Could not look up databases owned by '{}': {:#}",
+                        name, e
+                    );
+                    profile.databases.clone()
+                }
+            },
+        };
+        // Unknown ownership (`None`) is treated as at risk, not as owning nothing
+        if databases
+            .as_ref()
+            .is_none_or(|databases| !databases.is_empty())
+        {
+            owners.push((name.clone(), databases));
+        }
+    }
+    if owners.is_empty() {
+        return Ok(true);
+    }
+
+    println!("The following profiles still own databases, or could not be checked. Without their tokens you may lose access to them:");
+    for (name, databases) in &owners {
+        let Some(databases) = databases else {
+            println!("- {}: unknown (the lookup failed)", name);
+            continue;
+        };
+        let databases: Vec<String> = databases
+            .iter()
+            .map(|database| {
+                database
+                    .names
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| database.identity.clone())
+            })
+            .collect();
+        println!("- {}: {}", name, databases.join(", "));
+    }
+    if removal.force {
+        println!("--force given. Continuing without a backup.");
+        return Ok(true);
+    }
+
+    let theme = ColorfulTheme::default();
+    if Confirm::with_theme(&theme)
+        .with_prompt("Create an encrypted backup of these tokens first?")
+        .default(true)
+        .interact()
+        .context("Confirmation required. Run interactively or pass --force.")?
+    {
+        let owner_profiles = UserProfiles(
+            owners
+                .iter()
+                .map(|(name, _)| (name.clone(), profiles.0[name].clone()))
+                .collect(),
+        );
+        let path = write_backup(&owner_profiles)?;
+        println!(
+            "Encrypted backup written to {:?}. Restore it with 'restore'.",
+            path
+        );
+    }
+    Ok(Confirm::with_theme(&theme)
+        .with_prompt("Remove these profiles anyway?")
+        .default(false)
+        .interact()?)
+}
+
+fn write_backup(profiles: &UserProfiles) -> Result<PathBuf> {
+    let passphrase = Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Backup passphrase")
+        .with_confirmation("Repeat passphrase", "Passphrases do not match.")
+        .interact()?;
+    let plaintext =
+        toml::to_string_pretty(profiles).context("Failed to serialize profiles for backup")?;
+    let content = backup::encrypt(plaintext.as_bytes(), &passphrase)?;
+
+    let backups_dir = get_backups_dir()?;
+    fs::create_dir_all(&backups_dir)
+        .with_context(|| format!("Failed to create directory {:?}", backups_dir))?;
+    let path = backups_dir.join(format!("profiles-{}.toml", token::now_unix()));
+    fs::write(&path, content).with_context(|| format!("Failed to write backup to {:?}", path))?;
+    Ok(path)
+}
+
+/// The `setup` wizard. Each answer is checked as it is entered, and the
+/// settings as a whole before they are written.
+fn run_setup_wizard(mut settings: AppSettings) -> Result<()> {
+    let theme = ColorfulTheme::default();
+    let initial = settings_to_table(&settings)?;
+
+    println!("Step 1/4: SpacetimeDB CLI");
+    let mut version = None;
+    match spacetime_binary(&settings, None) {
+        Ok(binary) if binary.is_file() => match cli_version::CliVersion::detect(&binary) {
+            Ok(detected) => {
+                println!(
+                    "Found SpacetimeDB CLI {} at {}.",
+                    detected,
+                    binary.display()
+                );
+                version = Some(detected);
+            }
+            Err(_) => println!("Found {} (version unknown).", binary.display()),
+        },
+        _ => println!(
+            "Could not find the SpacetimeDB CLI ('{}').",
+            settings.spacetime_command
+        ),
+    }
+    let change_binary = version.is_none()
+        && Confirm::with_theme(&theme)
+            .with_prompt("Enter the command or path of the SpacetimeDB CLI now?")
+            .default(true)
+            .interact()?;
+    if change_binary {
+        settings.spacetime_command = Input::<String>::with_theme(&theme)
+            .with_prompt("SpacetimeDB CLI command or path")
+            .default(settings.spacetime_command.clone())
+            .validate_with(|input: &String| -> Result<(), String> {
+                let candidate = AppSettings {
+                    spacetime_command: input.trim().to_string(),
+                    ..AppSettings::default()
+                };
+                match spacetime_binary(&candidate, None) {
+                    Ok(binary) if binary.is_file() => Ok(()),
+                    Ok(binary) => Err(format!("{} does not exist.", binary.display())),
+                    Err(e) => Err(format!("{:#}", e)),
+                }
+            })
+            .interact_text()?
+            .trim()
+            .to_string();
+        version = spacetime_binary(&settings, None)
+            .and_then(|binary| cli_version::CliVersion::detect(&binary))
+            .ok();
+    }
+
+    println!("Step 2/4: SpacetimeDB CLI config");
+    let configured = get_cli_toml_path(&settings).ok();
+    let mut locations: Vec<PathBuf> = configured
+        .iter()
+        .filter(|path| path.is_file())
+        .cloned()
+        .collect();
+    for candidate in discovery::cli_config_candidates(&settings.cli_config_filename) {
+        if candidate.exists() && !candidate.is_legacy() && !locations.contains(&candidate.path) {
+            locations.push(candidate.path);
+        }
+    }
+    if discovery::cli_config_candidates(&settings.cli_config_filename)
+        .iter()
+        .any(|candidate| candidate.exists() && candidate.is_legacy())
+    {
+        println!(
+            "Found a pre-0.12 SpacetimeDB config. Run `migrate` afterwards to import its tokens."
+        );
+    }
+    let mut items: Vec<String> = locations
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    items.push("Another directory...".to_string());
+    let selection = Select::with_theme(&theme)
+        .with_prompt("Which cli.toml should be used?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    match locations.get(selection) {
+        Some(path) => {
+            if let Some(dir) = path.parent() {
+                settings.cli_config_dir_from_home = discovery::dir_setting(dir);
+            }
+            if let Some(filename) = path.file_name() {
+                settings.cli_config_filename = filename.to_string_lossy().into_owned();
+            }
+        }
+        None => {
+            settings.cli_config_dir_from_home = Input::<String>::with_theme(&theme)
+                .with_prompt("Directory (from home, absolute, or with ~/$VAR)")
+                .default(settings.cli_config_dir_from_home.clone())
+                .validate_with(|input: &String| -> Result<(), String> {
+                    match discovery::expand_dir(input.trim()) {
+                        Ok(dir) if dir.is_dir() => Ok(()),
+                        Ok(dir) => Err(format!("{} is not a directory.", dir.display())),
+                        Err(e) => Err(format!("{:#}", e)),
+                    }
+                })
+                .interact_text()?
+                .trim()
+                .to_string();
+            settings.cli_config_filename = Input::<String>::with_theme(&theme)
+                .with_prompt("Config filename")
+                .default(settings.cli_config_filename.clone())
+                .validate_with(|input: &String| validate_filename(input))
+                .interact_text()?
+                .trim()
+                .to_string();
+            if !get_cli_toml_path(&settings).is_ok_and(|path| path.exists()) {
+                println!("That file does not exist yet. It will be created on the first `switch`.");
+            }
+        }
+    }
+
+    println!("Step 3/4: Token location");
+    let mut default_key = settings.cli_token_key.clone();
+    if let Some(version) = version {
+        if let Some(warning) = version.layout_warning(&settings.cli_token_key) {
+            println!("{}", warning);
+            default_key = version.expected_token_key().to_string();
+        }
+    }
+    settings.cli_token_key = Input::<String>::with_theme(&theme)
+        .with_prompt("Token key in cli.toml")
+        .default(default_key)
+        .validate_with(|input: &String| -> Result<(), String> {
+            key_path::KeyPath::parse(input.trim())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .interact_text()?
+        .trim()
+        .to_string();
+    let methods = [LoginMethod::ServerIssued, LoginMethod::Web];
+    let method_names: Vec<String> = methods
+        .iter()
+        .filter_map(|method| method.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    let selection = Select::with_theme(&theme)
+        .with_prompt("Default login method for `create`")
+        .items(&method_names)
+        .default(
+            methods
+                .iter()
+                .position(|method| *method == settings.default_login_method)
+                .unwrap_or(0),
+        )
+        .interact()?;
+    settings.default_login_method = methods[selection];
+    settings.profiles_filename = Input::<String>::with_theme(&theme)
+        .with_prompt("Profiles filename")
+        .default(settings.profiles_filename.clone())
+        .validate_with(|input: &String| validate_filename(input))
+        .interact_text()?
+        .trim()
+        .to_string();
+
+    validate_settings(&settings)?;
+    // Only the answers that changed go into the user file, so values from the
+    // system, project and STT_* layers are not copied into it
+    let answers = settings_to_table(&settings)?;
+    let changes: Vec<(String, Option<toml::Value>)> = SettingKey::value_variants()
+        .iter()
+        .map(|key| key.name())
+        .filter(|name| answers.get(name) != initial.get(name))
+        .map(|name| {
+            let value = answers
+                .get(&name)
+                .cloned()
+                .unwrap_or_else(|| toml::Value::Array(Vec::new()));
+            (name, Some(value))
+        })
+        .collect();
+    if changes.is_empty() {
+        println!("Settings unchanged.");
+    } else {
+        write_user_settings(&changes)?;
+    }
+
+    println!("Step 4/4: Profiles and shell");
+    if let Ok(active_token) = read_active_token(&settings) {
+        let mut profiles = read_profiles(&settings)?;
+        match find_profile_by_token(&profiles, &active_token) {
+            Some(name) => println!("The active token is already stored as '{}'.", name),
+            None => {
+                if Confirm::with_theme(&theme)
+                    .with_prompt("Save the active token as a profile?")
+                    .default(true)
+                    .interact()?
+                {
+                    let profile_name = Input::<String>::with_theme(&theme)
+                        .with_prompt("Profile name")
+                        .default("default".to_string())
+                        .validate_with(|input: &String| -> Result<(), String> {
+                            if input.trim().is_empty() {
+                                Err("The name must not be empty.".to_string())
+                            } else if profiles.0.contains_key(input.trim()) {
+                                Err(format!("Profile '{}' already exists.", input.trim()))
+                            } else {
+                                Ok(())
+                            }
+                        })
+                        .interact_text()?
+                        .trim()
+                        .to_string();
+                    profiles
+                        .0
+                        .insert(profile_name.clone(), Profile::new(active_token));
+                    write_profiles(&settings, &profiles)?;
+                    println!("Saved the active token as '{}'.", profile_name);
+                }
+            }
+        }
+    }
+
+    let shell_program = std::env::var("SHELL").unwrap_or_default();
+    if let Some(shell) = shell::Shell::from_program(&shell_program) {
+        let exe = std::env::current_exe().context("Failed to locate this executable")?;
+        let integration = shell.integration(&exe.to_string_lossy());
+        match shell.startup_file(&shell_program) {
+            Some(startup_file) => {
+                let existing = fs::read_to_string(&startup_file).unwrap_or_default();
+                if existing.contains(shell::INTEGRATION_MARKER) {
+                    println!("Shell integration is already in {:?}.", startup_file);
+                } else if Confirm::with_theme(&theme)
+                    .with_prompt(format!(
+                        "Add `stt-use <profile>` (per-terminal profiles) to {:?}?",
+                        startup_file
+                    ))
+                    .default(false)
+                    .interact()?
+                {
+                    if let Some(parent_dir) = startup_file.parent() {
+                        fs::create_dir_all(parent_dir).with_context(|| {
+                            format!("Failed to create directory {:?}", parent_dir)
+                        })?;
+                    }
+                    // Keep a blank line between existing content and the block
+                    let separator = match existing.as_str() {
+                        "" => "",
+                        text if text.ends_with('\n') => "\n",
+                        _ => "\n\n",
+                    };
+                    fs::write(
+                        &startup_file,
+                        format!("{}{}{}", existing, separator, integration),
+                    )
+                    .with_context(|| format!("Failed to write {:?}", startup_file))?;
+                    println!(
+                        "Added shell integration to {:?}. Open a new terminal to use it.",
+                        startup_file
+                    );
+                }
+            }
+            None => println!(
+                "To select profiles per terminal, add this to your shell profile:\n{}",
+                integration
+            ),
+        }
+    }
+
+    println!("Setup complete.");
+    Ok(())
+}
+
+fn validate_filename(input: &str) -> Result<(), String> {
+    let input = input.trim();
+    if input.is_empty() {
+        Err("The filename must not be empty.".to_string())
+    } else if input.contains('/') || input.contains(std::path::MAIN_SEPARATOR) {
+        Err("Enter a filename, not a path.".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// The login flow that re-obtains `profile`'s token. Server-issued logins
+/// always mint a new identity, so they cannot refresh an existing one.
+fn refresh_flow(settings: &AppSettings, profile: &Profile) -> Result<LoginFlow> {
+    let flow = profile
+        .login
+        .clone()
+        .unwrap_or_else(|| LoginFlow::fallback(settings, profile.server.clone()));
+    if flow.method == LoginMethod::ServerIssued {
+        let source = if profile.login.is_some() {
+            "it was created with a server-issued login"
+        } else {
+            "it has no recorded login flow and default_login_method is server-issued"
+        };
+        anyhow::bail!(
+            "Cannot refresh: {}, which always creates a new identity. Create a new profile instead, or use `create --web` for profiles that can be refreshed.",
+            source
+        );
+    }
+    Ok(flow)
+}
+
+/// Runs the login flow of a stored profile and replaces its token, provided
+/// the new token belongs to the same identity as the old one.
+fn refresh_profile(settings: &AppSettings, profiles: &mut UserProfiles, name: &str) -> Result<()> {
+    let profile = profiles
+        .0
+        .get(name)
+        .with_context(|| format!("Profile '{}' not found.", name))?;
+    let old_identity = token::decode_claims(&profile.token)
+        .ok()
+        .and_then(|claims| claims.identity());
+    let flow = refresh_flow(settings, profile)?;
+
+    println!("Refreshing profile '{}'...", name);
+    run_login_flow(settings, Some(profile), &flow)?;
+    let new_token =
+        read_active_token(settings).context("Cannot read the new token after login.")?;
+    let new_identity = token::decode_claims(&new_token)
+        .context("Failed to decode the new token.")?
+        .identity();
+
+    match (old_identity, new_identity) {
+        (Some(old), Some(new)) if old != new => anyhow::bail!(
+            "The new token belongs to identity {} but profile '{}' has identity {}. The profile was not updated.",
+            new,
+            name,
+            old
+        ),
+        (Some(_), None) => anyhow::bail!(
+            "Could not determine the identity of the new token. The profile was not updated."
+        ),
+        (None, _) => println!(
+            "Warning: Could not determine the identity of the old token for '{}'. Skipping the identity check.",
+            name
+        ),
+        _ => {}
+    }
+
+    if let Some(profile) = profiles.0.get_mut(name) {
+        profile.token = new_token;
+    }
+    write_profiles(settings, profiles)?;
+    println!("Profile '{}' refreshed.", name);
+    Ok(())
+}
+
+fn mask_token(token: &str) -> String {
+    if token.len() <= 10 {
+        // Arbitrary length, too short to mask meaningfully
+        return token.to_string();
+    }
+    format!("{}...{}", &token[..5], &token[token.len() - 5..])
+}
+
+fn main() -> Result<()> {
+    if invoked_as_shim() {
+        let settings = load_app_settings().context("Failed to load application settings")?;
+        std::process::exit(run_shim(&settings)?);
+    }
+    let cli = Cli::parse();
+    // `config` is how invalid settings get repaired, so it must not need valid ones
+    if let Commands::Config(command) = cli.command {
+        return run_config_command(command);
+    }
+    let settings = load_app_settings().context("Failed to load application settings")?;
+
+    match cli.command {
+        Commands::Set(args) => {
+            let mut profiles = read_profiles(&settings)?; // Renamed
+            check_issuer_matches(&settings, &args.token, None, args.activation.allow_mismatch)?;
+            profiles // Renamed
+                .0
+                .entry(args.profile_name.clone())
+                .and_modify(|profile| profile.token = args.token.clone())
+                .or_insert_with(|| Profile::new(args.token.clone())); // Renamed
+            write_profiles(&settings, &profiles)?; // Renamed
+            println!(
+                "Profile '{}' saved/updated in {}.", // Renamed
+                args.profile_name,
+                settings.profiles_filename // Renamed
+            );
+
+            let mut cli_toml = read_or_create_cli_toml(&settings)?;
+            set_cli_token(&settings, &mut cli_toml, &args.token)?;
+            write_cli_toml(&settings, &cli_toml)?;
+            update_targets(&settings, &args.token);
+            println!(
+                "Profile '{}' also set as active token in {}.", // Renamed
+                args.profile_name,
+                settings.cli_config_filename // Renamed
+            );
+        }
+        Commands::Switch(args) => {
+            let profiles = read_profiles(&settings)?; // Renamed
+            let profile_name_to_switch = match args.profile_name {
+                // Renamed
+                Some(name) => name,
+                None => {
+                    if profiles.0.is_empty() {
+                        // Renamed
+                        println!(
+                            "No profiles found in {}. Cannot switch.", // Renamed
+                            settings.profiles_filename                 // Renamed
+                        );
+                        anyhow::bail!("No profiles available to switch."); // Renamed
+                    }
+                    let profile_names: Vec<&String> = profiles.0.keys().collect(); // Renamed
+                    let selection = Select::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Select profile to switch to") // Renamed
+                        .items(&profile_names) // Renamed
+                        .default(0)
+                        .interact_opt()?
+                        .context("No profile selected or selection cancelled.")?; // Renamed
+
+                    profile_names[selection].clone() // Renamed
+                }
+            };
+
+            if let Some(profile) = profiles.0.get(&profile_name_to_switch) {
+                // Renamed
+                check_issuer_matches(
+                    &settings,
+                    &profile.token,
+                    None,
+                    args.activation.allow_mismatch,
+                )?;
+                let mut cli_toml = read_or_create_cli_toml(&settings)?;
+                set_cli_token(&settings, &mut cli_toml, &profile.token)?; // Renamed
+                write_cli_toml(&settings, &cli_toml)?;
+                update_targets(&settings, &profile.token);
+                println!(
+                    "Switched active token to profile '{}' (from {}) in {}.", // Renamed
+                    profile_name_to_switch,                                   // Renamed
+                    settings.profiles_filename,                               // Renamed
+                    settings.cli_config_filename
+                );
+            } else {
+                println!(
+                    "Profile '{}' not found in {}. Cannot switch.", // Renamed
+                    profile_name_to_switch,
+                    settings.profiles_filename // Renamed
+                );
+                println!("Available profiles: {:?}", profiles.0.keys()); // Renamed
+                anyhow::bail!("Profile not found in profiles file for switching.");
+                // Renamed
+            }
+        }
+        Commands::Admin(args) => {
+            let admin_profile_name = "admin".to_string(); // Renamed
+            let profiles = read_profiles(&settings)?; // Renamed
+            if let Some(profile) = profiles.0.get(&admin_profile_name) {
+                // Renamed
+                check_issuer_matches(
+                    &settings,
+                    &profile.token,
+                    None,
+                    args.activation.allow_mismatch,
+                )?;
+                let mut cli_toml = read_or_create_cli_toml(&settings)?;
+                set_cli_token(&settings, &mut cli_toml, &profile.token)?; // Renamed
+                write_cli_toml(&settings, &cli_toml)?;
+                update_targets(&settings, &profile.token);
+                println!(
+                    "Switched active token to ADMIN profile '{}' (from {}) in {}.", // Renamed
+                    admin_profile_name,
+                    settings.profiles_filename,
+                    settings.cli_config_filename // Renamed
+                );
+            } else {
+                println!(
+                    "ADMIN profile ('{}') not found in {}. Cannot switch.", // Renamed
+                    admin_profile_name,
+                    settings.profiles_filename // Renamed
+                );
+                println!("Ensure a profile named 'admin' exists with a valid token."); // Renamed
+                anyhow::bail!("Admin profile not found."); // Renamed
+            }
+        }
+        Commands::Save(args) => {
+            let cli_toml_path = get_cli_toml_path(&settings)?;
             if !cli_toml_path.exists() {
                 anyhow::bail!(
                     "{} does not exist. Cannot save token.",
                     settings.cli_config_filename
                 );
             }
-            let cli_toml = read_cli_toml(&settings)?;
-
+            let cli_toml = read_cli_toml(&settings)?;
+
+            let mut profiles = read_profiles(&settings)?; // Renamed
+            if profiles.0.contains_key(&args.profile_name) {
+                // Renamed
+                anyhow::bail!("Profile '{}' already exists in {}. Use a different name or delete the existing one first.", args.profile_name, settings.profiles_filename);
+                // Renamed
+            }
+
+            match get_cli_token(&settings, &cli_toml)? {
+                Some(token_item) => {
+                    if let Some(token_str) = token_item.as_str() {
+                        profiles // Renamed
+                            .0
+                            .insert(
+                                args.profile_name.clone(),
+                                Profile::new(token_str.to_string()),
+                            ); // Renamed
+                        write_profiles(&settings, &profiles)?; // Renamed
+                        println!(
+                            "Saved current active token as '{}' in {}.", // Renamed
+                            args.profile_name,
+                            settings.profiles_filename // Renamed
+                        );
+                    } else {
+                        anyhow::bail!(
+                            "Token key '{}' in {} is not a string.",
+                            settings.cli_token_key,
+                            settings.cli_config_filename
+                        );
+                    }
+                }
+                None => {
+                    anyhow::bail!(
+                        "User is not logged in. Token key '{}' not found in {}.",
+                        settings.cli_token_key,
+                        settings.cli_config_filename
+                    );
+                }
+            }
+        }
+        Commands::Reset(args) => {
+            let existing = read_profiles(&settings)?;
+            let profile_names: Vec<String> = existing.0.keys().cloned().collect();
+            if !confirm_profile_removal(&settings, &existing, &profile_names, &args.removal)? {
+                anyhow::bail!("Reset aborted.");
+            }
+            let profiles = UserProfiles::default(); // Renamed
+            write_profiles(&settings, &profiles)?; // Renamed
+            println!("{} has been reset.", settings.profiles_filename); // Renamed
+        }
+        Commands::Create(args) => {
+            let mut profiles = read_profiles(&settings)?; // Renamed
+            if profiles.0.contains_key(&args.profile_name) {
+                // Renamed
+                anyhow::bail!(
+                    "Profile '{}' already exists in {}. Cannot create.", // Renamed
+                    args.profile_name,                                   // Renamed
+                    settings.profiles_filename                           // Renamed
+                );
+            }
+
+            let method = if args.web {
+                LoginMethod::Web
+            } else if args.server_issued {
+                LoginMethod::ServerIssued
+            } else {
+                settings.default_login_method
+            };
+            let flow = LoginFlow {
+                method,
+                server: args.server.clone(),
+                no_logout: args.no_logout,
+                extra_args: args.login_args.clone(),
+            };
+            run_login_flow(&settings, None, &flow)?;
+
+            println!(
+                "Login successful. Saving token as '{}'...",
+                args.profile_name // Renamed
+            );
+            let token = read_active_token(&settings).context("Cannot save token after login.")?;
+            profiles.0.insert(
+                args.profile_name.clone(),
+                Profile {
+                    token,
+                    server: args.server,
+                    login: Some(flow),
+                    ..Profile::default()
+                },
+            );
+            write_profiles(&settings, &profiles)?; // Renamed
+            println!(
+                "Successfully created and saved profile '{}' in {}.", // Renamed
+                args.profile_name,
+                settings.profiles_filename // Renamed
+            );
+        }
+        Commands::List(args) => {
+            let mut profiles = read_profiles(&settings)?; // Renamed
+            let mut active_token_opt: Option<String> = None;
+
+            if let Ok(cli_toml_path) = get_cli_toml_path(&settings) {
+                if cli_toml_path.exists() {
+                    if let Ok(cli_toml_doc) = read_cli_toml(&settings) {
+                        if let Ok(Some(token_item)) = get_cli_token(&settings, &cli_toml_doc) {
+                            if let Some(token_str) = token_item.as_str() {
+                                active_token_opt = Some(token_str.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+
+            if profiles.0.is_empty() {
+                // Renamed
+                println!("No profiles found in {}.", settings.profiles_filename);
+            // Renamed
+            } else {
+                println!("Available profiles in {}:", settings.profiles_filename); // Renamed
+                let mut sorted_profile_names: Vec<String> = profiles.0.keys().cloned().collect(); // Renamed
+                sorted_profile_names.sort(); // Renamed
+                let name_width = sorted_profile_names
+                    .iter()
+                    .map(String::len)
+                    .max()
+                    .unwrap_or(0);
+
+                for profile_name in &sorted_profile_names {
+                    // Renamed
+                    let mut display_name = format!("- {}", profile_name); // Renamed
+                    if args.with_databases {
+                        let count =
+                            match fetch_owned_databases(&settings, &profiles.0[profile_name]) {
+                                Ok(databases) => {
+                                    let count = databases.len().to_string();
+                                    if let Some(profile) = profiles.0.get_mut(profile_name) {
+                                        profile.databases = Some(databases);
+                                    }
+                                    count
+                                }
+                                Err(_) => "?".to_string(),
+                            };
+                        display_name = format!(
+                            "- {:<width$}  {:>3} database(s)",
+                            profile_name,
+                            count,
+                            width = name_width
+                        );
+                    }
+                    if let Some(ref active_token) = active_token_opt {
+                        if let Some(profile) = profiles.0.get(profile_name) {
+                            // Renamed
+                            if &profile.token == active_token {
+                                display_name.push_str(" (current)");
+                            }
+                        }
+                    }
+                    println!("{}", display_name);
+                }
+                if args.with_databases {
+                    write_profiles(&settings, &profiles)?;
+                }
+            }
+        }
+        Commands::Current => {
+            if let Some((profile_name, source)) = selected_profile() {
+                println!("Session profile: {} (from {})", profile_name, source);
+                let profiles = read_profiles(&settings)?;
+                match profiles.0.get(&profile_name) {
+                    Some(profile) => println!("Session token: {}", mask_token(&profile.token)),
+                    None => println!(
+                        "Warning: Profile '{}' not found in {}.",
+                        profile_name, settings.profiles_filename
+                    ),
+                }
+                println!(
+                    "The active token in {} is not used here.",
+                    settings.cli_config_filename
+                );
+                return Ok(());
+            }
+            let cli_toml_path = get_cli_toml_path(&settings)?;
+            if !cli_toml_path.exists() {
+                println!(
+                    "{} not found. No active token set.",
+                    settings.cli_config_filename
+                );
+                return Ok(());
+            }
+            let cli_toml_doc = read_cli_toml(&settings)?;
+            if let Some(token_item) = get_cli_token(&settings, &cli_toml_doc)? {
+                if let Some(active_token_str) = token_item.as_str() {
+                    let profiles = read_profiles(&settings)?; // Renamed
+                    let mut current_profile_name: Option<String> = None; // Renamed
+                    for (profile_name, profile) in profiles.0.iter() {
+                        // Renamed
+                        if profile.token == active_token_str {
+                            current_profile_name = Some(profile_name.clone()); // Renamed
+                            break;
+                        }
+                    }
+                    if let Some(name) = current_profile_name {
+                        // Renamed
+                        println!("Current active profile: {}", name); // Renamed
+                    } else {
+                        println!(
+                            "Current active token is set, but not found under any profile name in {}.", // Renamed
+                            settings.profiles_filename // Renamed
+                        );
+                    }
+                    println!("Active token: {}", mask_token(active_token_str));
+                } else {
+                    println!(
+                        "Active token key '{}' in {} is not a string.",
+                        settings.cli_token_key, settings.cli_config_filename
+                    );
+                }
+            } else {
+                println!(
+                    "No active token (key '{}') found in {}.",
+                    settings.cli_token_key, settings.cli_config_filename
+                );
+            }
+        }
+        Commands::Delete(args) => {
             let mut profiles = read_profiles(&settings)?; // Renamed
-            if profiles.0.contains_key(&args.profile_name) {
-                // Renamed
-                anyhow::bail!("Profile '{}' already exists in {}. Use a different name or delete the existing one first.", args.profile_name, settings.profiles_filename);
+            if profiles.0.contains_key(&args.profile_name)
+                && !confirm_profile_removal(
+                    &settings,
+                    &profiles,
+                    std::slice::from_ref(&args.profile_name),
+                    &args.removal,
+                )?
+            {
+                anyhow::bail!("Deletion aborted.");
+            }
+            if profiles.0.remove(&args.profile_name).is_some() {
                 // Renamed
+                write_profiles(&settings, &profiles)?; // Renamed
+                println!(
+                    "Profile '{}' deleted from {}.", // Renamed
+                    args.profile_name,
+                    settings.profiles_filename // Renamed
+                );
+            } else {
+                println!(
+                    "Profile '{}' not found in {}. Nothing to delete.", // Renamed
+                    args.profile_name,
+                    settings.profiles_filename // Renamed
+                );
+                anyhow::bail!("Profile not found for deletion."); // Renamed
             }
+        }
+        Commands::Refresh(args) => {
+            let mut profiles = read_profiles(&settings)?;
+            let mut profile_names = if args.all_expired {
+                let mut expired: Vec<String> = profiles
+                    .0
+                    .iter()
+                    .filter(|(_, profile)| {
+                        token::decode_claims(&profile.token)
+                            .map(|claims| claims.is_expired())
+                            .unwrap_or(false)
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                expired.sort();
+                if expired.is_empty() {
+                    println!(
+                        "No expired profiles found in {}.",
+                        settings.profiles_filename
+                    );
+                    return Ok(());
+                }
+                expired
+            } else {
+                let profile_name = args
+                    .profile_name
+                    .context("A profile name or --all-expired is required.")?;
+                if !profiles.0.contains_key(&profile_name) {
+                    anyhow::bail!(
+                        "Profile '{}' not found in {}. Cannot refresh.",
+                        profile_name,
+                        settings.profiles_filename
+                    );
+                }
+                vec![profile_name]
+            };
 
-            match cli_toml.get(&settings.cli_token_key) {
-                Some(token_item) => {
-                    if let Some(token_str) = token_item.as_str() {
-                        profiles // Renamed
-                            .0
-                            .insert(args.profile_name.clone(), token_str.to_string()); // Renamed
-                        write_profiles(&settings, &profiles)?; // Renamed
-                        println!(
-                            "Saved current active token as '{}' in {}.", // Renamed
-                            args.profile_name,
-                            settings.profiles_filename // Renamed
+            // Sort out profiles that cannot be refreshed before cli.toml is touched
+            let mut failed = Vec::new();
+            profile_names.retain(|name| match refresh_flow(&settings, &profiles.0[name]) {
+                Ok(_) => true,
+                Err(e) => {
+                    println!("Failed to refresh profile '{}': {:#}", name, e);
+                    failed.push(name.clone());
+                    false
+                }
+            });
+            if profile_names.is_empty() {
+                anyhow::bail!("Failed to refresh profile(s): {}", failed.join(", "));
+            }
+
+            let previous_token = read_active_token(&settings).ok();
+            let previous_profile = previous_token
+                .as_deref()
+                .and_then(|token| find_profile_by_token(&profiles, token))
+                .cloned();
+
+            for profile_name in &profile_names {
+                if let Err(e) = refresh_profile(&settings, &mut profiles, profile_name) {
+                    println!("Failed to refresh profile '{}': {:#}", profile_name, e);
+                    failed.push(profile_name.clone());
+                }
+            }
+
+            // The login flow overwrites the active token, so put back whatever was active
+            // before (using the new token if the active profile was itself refreshed).
+            let restore_token = match &previous_profile {
+                Some(name) => profiles.0.get(name).map(|profile| profile.token.clone()),
+                None => previous_token,
+            };
+            write_active_token(&settings, restore_token.as_deref())?;
+            match &previous_profile {
+                Some(name) => println!("Restored active profile '{}'.", name),
+                None => println!("Restored the previously active token."),
+            }
+
+            if !failed.is_empty() {
+                anyhow::bail!("Failed to refresh profile(s): {}", failed.join(", "));
+            }
+        }
+        Commands::NewIdentity(args) => {
+            let mut profiles = read_profiles(&settings)?;
+            if profiles.0.contains_key(&args.profile_name) {
+                anyhow::bail!(
+                    "Profile '{}' already exists in {}. Cannot create.",
+                    args.profile_name,
+                    settings.profiles_filename
+                );
+            }
+
+            let server_url = resolve_server_url(&settings, args.server.as_deref())?;
+            let client = api::ApiClient::new(&server_url);
+            println!("Requesting a new identity from {}...", client.base_url());
+            let new_identity = client.create_identity()?;
+            profiles.0.insert(
+                args.profile_name.clone(),
+                Profile {
+                    token: new_identity.token,
+                    server: Some(args.server.unwrap_or(server_url)),
+                    ..Profile::default()
+                },
+            );
+            write_profiles(&settings, &profiles)?;
+            println!(
+                "Saved new identity {} as profile '{}' in {}.",
+                new_identity.identity, args.profile_name, settings.profiles_filename
+            );
+        }
+        Commands::Pool(PoolCommands::Create(args)) => {
+            if args.count == 0 {
+                anyhow::bail!("--count must be at least 1.");
+            }
+            let mut profiles = read_profiles(&settings)?;
+            let width = args.count.to_string().len().max(3);
+            let names: Vec<String> = (1..=args.count)
+                .map(|i| format!("{}-{:0width$}", args.prefix, i, width = width))
+                .collect();
+            let existing: Vec<&String> = names
+                .iter()
+                .filter(|name| profiles.0.contains_key(*name))
+                .collect();
+            if !existing.is_empty() {
+                anyhow::bail!(
+                    "Profile(s) already exist in {}: {:?}. Delete the pool first or use a different prefix.",
+                    settings.profiles_filename,
+                    existing
+                );
+            }
+
+            let server_url = resolve_server_url(&settings, args.server.as_deref())?;
+            let client = api::ApiClient::new(&server_url);
+            let mut tags = vec![pool_tag(&args.prefix)];
+            tags.extend(args.tag.clone());
+            println!(
+                "Requesting {} identities from {}...",
+                args.count,
+                client.base_url()
+            );
+
+            let mut members = Vec::new();
+            let mut result = Ok(());
+            for name in names {
+                match client.create_identity() {
+                    Ok(new_identity) => {
+                        profiles.0.insert(
+                            name.clone(),
+                            Profile {
+                                token: new_identity.token.clone(),
+                                server: Some(
+                                    args.server.clone().unwrap_or_else(|| server_url.clone()),
+                                ),
+                                tags: tags.clone(),
+                                ..Profile::default()
+                            },
                         );
-                    } else {
+                        members.push(PoolMember {
+                            name,
+                            identity: new_identity.identity,
+                            token: new_identity.token,
+                        });
+                    }
+                    Err(e) => {
+                        result =
+                            Err(e.context(format!("Failed to create identity for '{}'", name)));
+                        break;
+                    }
+                }
+            }
+            // Keep whatever was minted before a failure so it can be cleaned up with `pool delete`
+            if !members.is_empty() {
+                write_profiles(&settings, &profiles)?;
+            }
+            result?;
+
+            let output = args
+                .output
+                .unwrap_or_else(|| PathBuf::from(format!("{}.json", args.prefix)));
+            let fixture = serde_json::to_string_pretty(&members)
+                .context("Failed to serialize the pool fixture to JSON")?;
+            keys::write_private(&output, fixture.as_bytes())
+                .with_context(|| format!("Failed to write pool fixture to {:?}", output))?;
+            println!(
+                "Created pool '{}' with {} identities (tag '{}'). Fixture written to {:?}.",
+                args.prefix,
+                members.len(),
+                pool_tag(&args.prefix),
+                output
+            );
+            println!(
+                "The fixture contains plaintext tokens and is readable only by you. Keep it out of version control."
+            );
+        }
+        Commands::Pool(PoolCommands::Delete(args)) => {
+            let mut profiles = read_profiles(&settings)?;
+            let tag = pool_tag(&args.prefix);
+            let mut members: Vec<String> = profiles
+                .0
+                .iter()
+                .filter(|(_, profile)| profile.has_tag(&tag))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if members.is_empty() {
+                anyhow::bail!(
+                    "No profiles tagged '{}' found in {}.",
+                    tag,
+                    settings.profiles_filename
+                );
+            }
+            members.sort();
+            if !confirm_profile_removal(&settings, &profiles, &members, &args.removal)? {
+                anyhow::bail!("Deletion aborted.");
+            }
+            for name in &members {
+                profiles.0.remove(name);
+            }
+            write_profiles(&settings, &profiles)?;
+            println!(
+                "Deleted {} profile(s) of pool '{}' from {}.",
+                members.len(),
+                args.prefix,
+                settings.profiles_filename
+            );
+        }
+        Commands::Check(args) => {
+            let mut profiles = read_profiles(&settings)?;
+            let mut profile_names: Vec<String> = match args.profile_name {
+                Some(name) => {
+                    if !profiles.0.contains_key(&name) {
                         anyhow::bail!(
-                            "Token key '{}' in {} is not a string.",
-                            settings.cli_token_key,
-                            settings.cli_config_filename
+                            "Profile '{}' not found in {}.",
+                            name,
+                            settings.profiles_filename
                         );
                     }
+                    vec![name]
                 }
-                None => {
-                    anyhow::bail!(
-                        "User is not logged in. Token key '{}' not found in {}.",
-                        settings.cli_token_key,
-                        settings.cli_config_filename
+                None => profiles.0.keys().cloned().collect(),
+            };
+            profile_names.sort();
+            if profile_names.is_empty() {
+                println!("No profiles found in {}.", settings.profiles_filename);
+                return Ok(());
+            }
+
+            let mut server_urls: HashMap<Option<String>, Result<String>> = HashMap::new();
+            let mut problems = 0;
+            for name in &profile_names {
+                let Some(profile) = profiles.0.get_mut(name) else {
+                    continue;
+                };
+                let claims = match token::decode_claims(&profile.token) {
+                    Ok(claims) => claims,
+                    Err(e) => {
+                        println!("- {}: invalid (cannot decode token: {:#})", name, e);
+                        problems += 1;
+                        continue;
+                    }
+                };
+                let expiry = claims.describe_expiry();
+                if !args.online {
+                    if claims.is_expired() {
+                        println!("- {}: expired ({})", name, expiry);
+                        problems += 1;
+                    } else {
+                        println!("- {}: ok ({})", name, expiry);
+                    }
+                    continue;
+                }
+
+                let Some(identity) = claims.identity() else {
+                    println!("- {}: invalid (token has no identity claim)", name);
+                    problems += 1;
+                    continue;
+                };
+                let server = args.server.clone().or_else(|| profile.server.clone());
+                let server_url = match server_urls
+                    .entry(server.clone())
+                    .or_insert_with(|| resolve_server_url(&settings, server.as_deref()))
+                {
+                    Ok(url) => url.clone(),
+                    Err(e) => {
+                        println!("- {}: unreachable ({:#})", name, e);
+                        problems += 1;
+                        continue;
+                    }
+                };
+                match verify_profile(&api::ApiClient::new(&server_url), &identity, profile) {
+                    Ok(api::Verification::Valid) => {
+                        println!("- {}: valid on {} ({})", name, server_url, expiry);
+                    }
+                    Ok(api::Verification::IdentityMismatch) => {
+                        println!(
+                            "- {}: invalid on {} (token does not belong to identity {})",
+                            name, server_url, identity
+                        );
+                        problems += 1;
+                    }
+                    Ok(api::Verification::Rejected(code)) => {
+                        println!(
+                            "- {}: invalid on {} (rejected with HTTP {}, {})",
+                            name, server_url, code, expiry
+                        );
+                        problems += 1;
+                    }
+                    Err(e) => {
+                        println!("- {}: unreachable ({:#})", name, e);
+                        problems += 1;
+                    }
+                }
+            }
+
+            if args.online {
+                write_profiles(&settings, &profiles)?;
+            }
+            if problems > 0 {
+                anyhow::bail!(
+                    "{} of {} profile(s) failed the check.",
+                    problems,
+                    profile_names.len()
+                );
+            }
+        }
+        Commands::Databases(args) => {
+            let mut profiles = read_profiles(&settings)?;
+            let profile_name = resolve_profile_name(&settings, &profiles, args.profile_name)?;
+            let databases = fetch_owned_databases(&settings, &profiles.0[&profile_name])?;
+
+            if databases.is_empty() {
+                println!("Profile '{}' owns no databases.", profile_name);
+            } else {
+                println!("Databases owned by profile '{}':", profile_name);
+                for database in &databases {
+                    let names = if database.names.is_empty() {
+                        "(unnamed)".to_string()
+                    } else {
+                        database.names.join(", ")
+                    };
+                    println!("- {}  {}", names, database.identity);
+                }
+            }
+            if let Some(profile) = profiles.0.get_mut(&profile_name) {
+                profile.databases = Some(databases);
+            }
+            write_profiles(&settings, &profiles)?;
+        }
+        Commands::Restore(args) => {
+            let content = fs::read_to_string(&args.backup_file)
+                .with_context(|| format!("Failed to read backup {:?}", args.backup_file))?;
+            let passphrase = Password::with_theme(&ColorfulTheme::default())
+                .with_prompt("Backup passphrase")
+                .interact()?;
+            let plaintext = backup::decrypt(&content, &passphrase)?;
+            let restored: UserProfiles = toml::from_str(
+                std::str::from_utf8(&plaintext).context("Backup does not contain valid UTF-8.")?,
+            )
+            .context("Failed to parse the profiles in the backup.")?;
+
+            let mut profiles = read_profiles(&settings)?;
+            let mut restored_names = Vec::new();
+            for (name, profile) in restored.0 {
+                if profiles.0.contains_key(&name) {
+                    println!(
+                        "Skipping '{}': a profile with that name already exists.",
+                        name
                     );
+                    continue;
                 }
+                profiles.0.insert(name.clone(), profile);
+                restored_names.push(name);
             }
+            restored_names.sort();
+            if !restored_names.is_empty() {
+                write_profiles(&settings, &profiles)?;
+            }
+            println!(
+                "Restored {} profile(s): {}",
+                restored_names.len(),
+                restored_names.join(", ")
+            );
         }
-        Commands::Reset => {
-            let profiles = UserProfiles::default(); // Renamed
-            write_profiles(&settings, &profiles)?; // Renamed
-            println!("{} has been reset.", settings.profiles_filename); // Renamed
+        Commands::WsToken(args) => {
+            let profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get(&args.profile_name).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    args.profile_name, settings.profiles_filename
+                )
+            })?;
+            let server = args.server.as_deref().or(profile.server.as_deref());
+            let server_url = resolve_server_url(&settings, server)?;
+            println!(
+                "{}",
+                ws_token_output(&args.profile_name, profile, &server_url, args.json)?
+            );
         }
-        Commands::Create(args) => {
-            let mut profiles = read_profiles(&settings)?; // Renamed
+        Commands::Keygen(args) => {
+            let path = match args.out {
+                Some(path) => path,
+                None => default_issuer_key_path()?,
+            };
+            if path.exists() && !args.force {
+                anyhow::bail!(
+                    "A key already exists at {:?}. Use --force to replace it.",
+                    path
+                );
+            }
+            let key = keys::IssuerKey::generate();
+            key.save(&path)?;
+            println!("Wrote ES256 private key {} to {:?}.", key.key_id(), path);
+            println!(
+                "Public key (JWK): {}",
+                serde_json::to_string(&key.public_jwk())
+                    .context("Failed to serialize public key")?
+            );
+        }
+        Commands::Mint(args) => {
+            let mut profiles = read_profiles(&settings)?;
             if profiles.0.contains_key(&args.profile_name) {
-                // Renamed
                 anyhow::bail!(
-                    "Profile '{}' already exists in {}. Cannot create.", // Renamed
-                    args.profile_name,                                   // Renamed
-                    settings.profiles_filename                           // Renamed
+                    "Profile '{}' already exists in {}. Cannot mint.",
+                    args.profile_name,
+                    settings.profiles_filename
+                );
+            }
+            let key_path = match args.key {
+                Some(path) => path,
+                None => default_issuer_key_path()?,
+            };
+            if !key_path.exists() {
+                anyhow::bail!(
+                    "No private key at {:?}. Create one with 'keygen' or pass --key.",
+                    key_path
+                );
+            }
+            let key = keys::IssuerKey::load(&key_path)?;
+
+            let identity = token::identity_from_claims(&args.issuer, &args.sub);
+            let now = token::now_unix();
+            let mut claims = serde_json::json!({
+                "hex_identity": identity,
+                "iss": args.issuer,
+                "sub": args.sub,
+                "iat": now,
+            });
+            if let Some(ttl) = &args.ttl {
+                let exp = now
+                    .checked_add(token::parse_duration(ttl)?)
+                    .with_context(|| format!("Invalid duration '{}': too long.", ttl))?;
+                claims["exp"] = exp.into();
+            }
+            if !args.audience.is_empty() {
+                claims["aud"] = args.audience.into();
+            }
+            let minted = key.sign_jwt(&claims)?;
+
+            profiles
+                .0
+                .insert(args.profile_name.clone(), Profile::new(minted));
+            write_profiles(&settings, &profiles)?;
+            println!(
+                "Minted token for '{}' (issuer '{}') and saved it as profile '{}'.",
+                claims["sub"].as_str().unwrap_or_default(),
+                claims["iss"].as_str().unwrap_or_default(),
+                args.profile_name
+            );
+            println!("Identity: {}", identity);
+        }
+        Commands::Issuer(IssuerCommands::Serve(args)) => {
+            let key = load_or_create_issuer_key()?;
+            let issuer_url = args
+                .issuer_url
+                .unwrap_or_else(|| format!("http://localhost:{}", args.port))
+                .trim_end_matches('/')
+                .to_string();
+            let address = format!("{}:{}", args.bind, args.port);
+            println!(
+                "Serving issuer '{}' (key {}) on http://{}. Press Ctrl+C to stop.",
+                issuer_url,
+                key.key_id(),
+                address
+            );
+            println!(
+                "Mint tokens for it with: spacetime-token mint <PROFILE_NAME> --issuer {} --sub <SUBJECT>",
+                issuer_url
+            );
+            issuer::serve(&address, &issuer_url, &key)?;
+        }
+        Commands::Issuer(IssuerCommands::Jwks) => {
+            let key = load_or_create_issuer_key()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&issuer::jwks(&key))
+                    .context("Failed to serialize JWKS")?
+            );
+        }
+        Commands::Context(ContextCommands::Create(args)) => {
+            let profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get(&args.profile).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    args.profile, settings.profiles_filename
+                )
+            })?;
+            let server = args
+                .server
+                .or_else(|| profile.server.clone())
+                .unwrap_or_else(|| DEFAULT_LOGIN_SERVER.to_string());
+            // Fail early on servers cli.toml doesn't know about
+            let server_url = resolve_server_url(&settings, Some(&server))?;
+            // `context use` writes the server as default_server, which must be a nickname
+            let server = if server.contains("://") {
+                let cli_toml_path = get_cli_toml_path(&settings)?;
+                let cli_toml = if cli_toml_path.exists() {
+                    read_cli_toml(&settings)?
+                } else {
+                    DocumentMut::new()
+                };
+                known_servers(&cli_toml)
+                    .into_iter()
+                    .find(|(_, url)| *url == server_url)
+                    .map(|(nickname, _)| nickname)
+                    .with_context(|| {
+                        format!(
+                            "No server nickname in {} points at {}. Add one with 'server add <NICKNAME> {}' and use the nickname.",
+                            settings.cli_config_filename, server_url, server_url
+                        )
+                    })?
+            } else {
+                server
+            };
+
+            let mut contexts = read_contexts(&settings)?;
+            contexts.contexts.insert(
+                args.context_name.clone(),
+                ContextEntry {
+                    profile: args.profile,
+                    server,
+                    database: args.database,
+                },
+            );
+            write_contexts(&settings, &contexts)?;
+            println!(
+                "Context '{}' saved. Activate it with 'context use {}'.",
+                args.context_name, args.context_name
+            );
+        }
+        Commands::Context(ContextCommands::Use(args)) => {
+            let mut contexts = read_contexts(&settings)?;
+            let context = contexts
+                .contexts
+                .get(&args.context_name)
+                .cloned()
+                .with_context(|| {
+                    format!(
+                        "Context '{}' not found in {}.",
+                        args.context_name, settings.contexts_filename
+                    )
+                })?;
+            let profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get(&context.profile).with_context(|| {
+                format!(
+                    "Context '{}' refers to profile '{}', which is not in {}.",
+                    args.context_name, context.profile, settings.profiles_filename
+                )
+            })?;
+            // A URL is not written to default_server below, so the token
+            // will be used against the current default server instead
+            let server = Some(context.server.as_str()).filter(|server| !server.contains("://"));
+            check_issuer_matches(
+                &settings,
+                &profile.token,
+                server,
+                args.activation.allow_mismatch,
+            )?;
+
+            let mut cli_toml = read_or_create_cli_toml(&settings)?;
+            set_cli_token(&settings, &mut cli_toml, &profile.token)?;
+            // Contexts saved before URLs were rejected may still hold one
+            if context.server.contains("://") {
+                println!(
+                    "Warning: server '{}' is a URL, not a nickname; leaving default_server unchanged.",
+                    context.server
+                );
+            } else {
+                cli_toml["default_server"] = Item::Value(context.server.clone().into());
+            }
+            write_cli_toml(&settings, &cli_toml)?;
+            update_targets(&settings, &profile.token);
+            contexts.current = Some(args.context_name.clone());
+            write_contexts(&settings, &contexts)?;
+            println!(
+                "Switched to context '{}' (profile '{}' on server '{}'{}).",
+                args.context_name,
+                context.profile,
+                context.server,
+                context
+                    .database
+                    .as_ref()
+                    .map(|database| format!(", database '{}'", database))
+                    .unwrap_or_default()
+            );
+        }
+        Commands::Context(ContextCommands::List) => {
+            let contexts = read_contexts(&settings)?;
+            if contexts.contexts.is_empty() {
+                println!("No contexts found in {}.", settings.contexts_filename);
+            } else {
+                println!("Available contexts in {}:", settings.contexts_filename);
+                let mut names: Vec<&String> = contexts.contexts.keys().collect();
+                names.sort();
+                for name in names {
+                    let context = &contexts.contexts[name];
+                    let mut line = format!(
+                        "- {}: profile '{}', server '{}'",
+                        name, context.profile, context.server
+                    );
+                    if let Some(database) = &context.database {
+                        line.push_str(&format!(", database '{}'", database));
+                    }
+                    if contexts.current.as_ref() == Some(name) {
+                        line.push_str(" (current)");
+                    }
+                    println!("{}", line);
+                }
+            }
+        }
+        Commands::Context(ContextCommands::Delete(args)) => {
+            let mut contexts = read_contexts(&settings)?;
+            if contexts.contexts.remove(&args.context_name).is_none() {
+                anyhow::bail!(
+                    "Context '{}' not found in {}. Nothing to delete.",
+                    args.context_name,
+                    settings.contexts_filename
                 );
             }
+            if contexts.current.as_ref() == Some(&args.context_name) {
+                contexts.current = None;
+            }
+            write_contexts(&settings, &contexts)?;
+            println!(
+                "Context '{}' deleted from {}.",
+                args.context_name, settings.contexts_filename
+            );
+        }
+        Commands::Exec(args) => {
+            let contexts = read_contexts(&settings)?;
+            let database = contexts
+                .current
+                .as_ref()
+                .and_then(|name| contexts.contexts.get(name))
+                .and_then(|context| context.database.clone())
+                .filter(|_| !args.no_database);
 
-            run_external_command(SPACETIME_CLI_COMMAND, &["logout"])
-                .context("Failed to logout from SpacetimeDB CLI.")?;
+            let mut spacetime_args = args.spacetime_args;
+            if let Some(database) = database {
+                insert_context_database(&mut spacetime_args, database);
+            }
 
+            std::process::exit(run_spacetime(&settings, &spacetime_args, true)?);
+        }
+        Commands::Snippet(args) => {
+            let profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get(&args.profile_name).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    args.profile_name, settings.profiles_filename
+                )
+            })?;
+            let server = args.server.as_deref().or(profile.server.as_deref());
+            let server_url = resolve_server_url(&settings, server)?;
+            let module = match args.module {
+                Some(module) => module,
+                None => {
+                    let contexts = read_contexts(&settings)?;
+                    contexts
+                        .current
+                        .as_ref()
+                        .and_then(|name| contexts.contexts.get(name))
+                        .and_then(|context| context.database.clone())
+                        .context(
+                            "No module name given and the current context has no database. Pass --module.",
+                        )?
+                }
+            };
             println!(
-                "Please follow the prompts from 'spacetime login --server-issued-login local'."
+                "{}",
+                snippet::render(
+                    args.lang,
+                    &server_url,
+                    &module,
+                    &profile.token,
+                    args.token_env.as_deref()
+                )
             );
-            run_external_command(
+        }
+        Commands::Foreach(args) => {
+            let profiles = read_profiles(&settings)?;
+            let mut selected: Vec<(&String, &Profile)> = profiles
+                .0
+                .iter()
+                .filter(|(_, profile)| args.tag.as_ref().is_none_or(|tag| profile.has_tag(tag)))
+                .collect();
+            selected.sort_by(|a, b| a.0.cmp(b.0));
+            if selected.is_empty() {
+                match &args.tag {
+                    Some(tag) => anyhow::bail!("No profiles are tagged '{}'.", tag),
+                    None => anyhow::bail!("No profiles found in {}.", settings.profiles_filename),
+                }
+            }
+
+            println!(
+                "Running '{} {}' as {} profile(s)...",
                 SPACETIME_CLI_COMMAND,
-                &["login", "--server-issued-login", "local"],
-            )
-            .context("Failed during 'spacetime login --server-issued-login local'.")?;
+                args.spacetime_args.join(" "),
+                selected.len()
+            );
+            let next = std::sync::atomic::AtomicUsize::new(0);
+            let outcomes: Vec<std::sync::Mutex<Option<ForeachOutcome>>> =
+                selected.iter().map(|_| Default::default()).collect();
+            let jobs = (args.jobs as usize).min(selected.len());
+            std::thread::scope(|scope| {
+                for _ in 0..jobs {
+                    scope.spawn(|| loop {
+                        let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some((_, profile)) = selected.get(index) else {
+                            break;
+                        };
+                        let outcome = run_as_profile(&settings, profile, &args.spacetime_args);
+                        if let Ok(mut slot) = outcomes[index].lock() {
+                            *slot = Some(outcome);
+                        }
+                    });
+                }
+            });
+
+            let mut failed = Vec::new();
+            for ((name, _), outcome) in selected.iter().zip(outcomes) {
+                let Some(outcome) = outcome.into_inner().ok().flatten() else {
+                    // Never counted as a success: the profile's run did not report back
+                    failed.push(name.to_string());
+                    println!("\n== {} (error: no result)", name);
+                    continue;
+                };
+                let status = match &outcome.status {
+                    Ok(status) if status.success() => "ok".to_string(),
+                    Ok(status) => {
+                        failed.push(name.to_string());
+                        match status.code() {
+                            Some(code) => format!("exit {}", code),
+                            None => "terminated by signal".to_string(),
+                        }
+                    }
+                    Err(e) => {
+                        failed.push(name.to_string());
+                        format!("error: {:#}", e)
+                    }
+                };
+                println!("\n== {} ({})", name, status);
+                for line in outcome.stdout.lines().chain(outcome.stderr.lines()) {
+                    println!("  {}", line);
+                }
+            }
 
             println!(
-                "Login successful. Saving token as '{}'...",
-                args.profile_name // Renamed
+                "\n{} succeeded, {} failed.",
+                selected.len() - failed.len(),
+                failed.len()
             );
-            let cli_toml_path = get_cli_toml_path(&settings)?;
-            if !cli_toml_path.exists() {
-                anyhow::bail!(
-                    "{} does not exist after login. Cannot save token.",
-                    settings.cli_config_filename
-                );
+            if !failed.is_empty() {
+                anyhow::bail!("Command failed for: {}", failed.join(", "));
             }
-            let cli_toml = read_cli_toml(&settings)?;
-            match cli_toml.get(&settings.cli_token_key) {
-                Some(token_item) => {
-                    if let Some(token_str) = token_item.as_str() {
-                        profiles // Renamed
-                            .0
-                            .insert(args.profile_name.clone(), token_str.to_string()); // Renamed
-                        write_profiles(&settings, &profiles)?; // Renamed
-                        println!(
-                            "Successfully created and saved profile '{}' in {}.", // Renamed
-                            args.profile_name,
-                            settings.profiles_filename // Renamed
-                        );
-                    } else {
-                        anyhow::bail!(
-                            "Token key '{}' in {} is not a string after login.",
-                            settings.cli_token_key,
-                            settings.cli_config_filename
-                        );
-                    }
+        }
+        Commands::Pin(args) => {
+            let cwd = std::env::current_dir().context("Failed to get current directory")?;
+            if args.clear {
+                if pin::remove(&cwd)? {
+                    println!("Removed the pin from {:?}.", cwd);
+                } else {
+                    println!("{:?} has no pin.", cwd);
                 }
-                None => {
+            } else if let Some(profile_name) = args.profile_name {
+                let profiles = read_profiles(&settings)?;
+                if !profiles.0.contains_key(&profile_name) {
                     anyhow::bail!(
-                        "Token key '{}' not found in {} after login.",
-                        settings.cli_token_key,
-                        settings.cli_config_filename
+                        "Profile '{}' not found in {}.",
+                        profile_name,
+                        settings.profiles_filename
                     );
                 }
+                let path = pin::write(&cwd, &profile_name)?;
+                println!("Pinned profile '{}' in {:?}.", profile_name, path);
+            } else {
+                match pin::find(&cwd) {
+                    Some(pin) => println!("'{}' (from {:?})", pin.profile_name, pin.path),
+                    None => println!("No profile is pinned here."),
+                }
             }
         }
-        Commands::List => {
-            let profiles = read_profiles(&settings)?; // Renamed
-            let mut active_token_opt: Option<String> = None;
-
-            if let Ok(cli_toml_path) = get_cli_toml_path(&settings) {
-                if cli_toml_path.exists() {
-                    if let Ok(cli_toml_doc) = read_cli_toml(&settings) {
-                        if let Some(token_item) = cli_toml_doc.get(&settings.cli_token_key) {
-                            if let Some(token_str) = token_item.as_str() {
-                                active_token_opt = Some(token_str.to_string());
-                            }
-                        }
-                    }
+        Commands::Shim(ShimCommands::Install(args)) => {
+            let own_exe = std::env::current_exe().context("Failed to locate this executable")?;
+            let link = args.dir.join(format!(
+                "{}{}",
+                SPACETIME_CLI_COMMAND,
+                std::env::consts::EXE_SUFFIX
+            ));
+            if link.symlink_metadata().is_ok() {
+                if !args.force {
+                    anyhow::bail!("{:?} already exists. Pass --force to replace it.", link);
                 }
+                fs::remove_file(&link).with_context(|| format!("Failed to remove {:?}", link))?;
             }
+            fs::create_dir_all(&args.dir)
+                .with_context(|| format!("Failed to create directory {:?}", args.dir))?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&own_exe, &link)
+                .with_context(|| format!("Failed to create link {:?}", link))?;
+            #[cfg(not(unix))]
+            fs::copy(&own_exe, &link).with_context(|| format!("Failed to copy to {:?}", link))?;
+            println!("Installed shim at {:?}.", link);
 
-            if profiles.0.is_empty() {
-                // Renamed
-                println!("No profiles found in {}.", settings.profiles_filename);
-            // Renamed
-            } else {
-                println!("Available profiles in {}:", settings.profiles_filename); // Renamed
-                let mut sorted_profile_names: Vec<_> = profiles.0.keys().collect(); // Renamed
-                sorted_profile_names.sort(); // Renamed
-
-                for profile_name in sorted_profile_names {
-                    // Renamed
-                    let mut display_name = format!("- {}", profile_name); // Renamed
-                    if let Some(ref active_token) = active_token_opt {
-                        if let Some(user_token) = profiles.0.get(profile_name) {
-                            // Renamed
-                            if user_token == active_token {
-                                display_name.push_str(" (current)");
-                            }
-                        }
-                    }
-                    println!("{}", display_name);
-                }
+            match spacetime_binary(&settings, None) {
+                Ok(real) => println!("The shim runs {:?}.", real),
+                Err(e) => println!("Warning: {:#}", e),
+            }
+            let path_var = std::env::var_os("PATH").unwrap_or_default();
+            let dir = fs::canonicalize(&args.dir).unwrap_or(args.dir.clone());
+            let position = std::env::split_paths(&path_var)
+                .position(|entry| fs::canonicalize(&entry).unwrap_or(entry) == dir);
+            let real_position = std::env::split_paths(&path_var).position(|entry| {
+                entry
+                    .join(format!(
+                        "{}{}",
+                        SPACETIME_CLI_COMMAND,
+                        std::env::consts::EXE_SUFFIX
+                    ))
+                    .is_file()
+                    && fs::canonicalize(&entry).unwrap_or(entry) != dir
+            });
+            match (position, real_position) {
+                (None, _) => println!(
+                    "Add {:?} to the front of your PATH to use the shim.",
+                    args.dir
+                ),
+                (Some(shim), Some(real)) if real < shim => println!(
+                    "Warning: {:?} comes after the real '{}' in your PATH, so the shim will not be used.",
+                    args.dir, SPACETIME_CLI_COMMAND
+                ),
+                _ => {}
             }
         }
-        Commands::Current => {
-            let cli_toml_path = get_cli_toml_path(&settings)?;
-            if !cli_toml_path.exists() {
-                println!(
-                    "{} not found. No active token set.",
+        Commands::Shim(ShimCommands::Status) => {
+            match selected_profile() {
+                Some((profile_name, source)) => {
+                    println!("Profile: '{}' (from {})", profile_name, source)
+                }
+                None => println!(
+                    "Profile: none selected, the active token in {} is used",
                     settings.cli_config_filename
-                );
+                ),
+            }
+            let selected = load_selected_profile(&settings)?;
+            match spacetime_binary(&settings, selected.as_ref().map(|(_, profile)| profile)) {
+                Ok(real) => println!("Binary: {:?}", real),
+                Err(e) => println!("Binary: {:#}", e),
+            }
+        }
+        Commands::Env(args) => {
+            if args.unset {
+                println!("{}", args.shell.unset(PROFILE_ENV_VAR));
+                println!("{}", args.shell.unset(TOKEN_ENV_VAR));
                 return Ok(());
             }
-            let cli_toml_doc = read_cli_toml(&settings)?;
-            if let Some(token_item) = cli_toml_doc.get(&settings.cli_token_key) {
-                if let Some(active_token_str) = token_item.as_str() {
-                    let profiles = read_profiles(&settings)?; // Renamed
-                    let mut current_profile_name: Option<String> = None; // Renamed
-                    for (profile_name, token) in profiles.0.iter() {
-                        // Renamed
-                        if token == active_token_str {
-                            current_profile_name = Some(profile_name.clone()); // Renamed
-                            break;
-                        }
-                    }
-                    if let Some(name) = current_profile_name {
-                        // Renamed
-                        println!("Current active profile: {}", name); // Renamed
-                    } else {
-                        println!(
-                            "Current active token is set, but not found under any profile name in {}.", // Renamed
-                            settings.profiles_filename // Renamed
-                        );
+            let profile_name = args
+                .profile_name
+                .context("A profile name or --unset is required.")?;
+            let profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get(&profile_name).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    profile_name, settings.profiles_filename
+                )
+            })?;
+            println!("{}", args.shell.export(PROFILE_ENV_VAR, &profile_name));
+            println!("{}", args.shell.export(TOKEN_ENV_VAR, &profile.token));
+        }
+        Commands::Prompt => {
+            // Meant to run on every prompt, so print nothing rather than fail
+            let profile_name = selected_profile().map(|(name, _)| name).or_else(|| {
+                let token = read_active_token(&settings).ok()?;
+                let profiles = read_profiles(&settings).ok()?;
+                find_profile_by_token(&profiles, &token).cloned()
+            });
+            if let Some(profile_name) = profile_name {
+                println!("{}", profile_name);
+            }
+        }
+        Commands::Binary(BinaryCommands::Show(args)) => {
+            let profiles = read_profiles(&settings)?;
+            let profile = match &args.profile_name {
+                Some(name) => Some(profiles.0.get(name).with_context(|| {
+                    format!(
+                        "Profile '{}' not found in {}.",
+                        name, settings.profiles_filename
+                    )
+                })?),
+                None => None,
+            };
+            let spacetime = spacetime_binary(&settings, profile)?;
+            println!("Binary: {}", spacetime.display());
+            let version = cli_version::CliVersion::detect(&spacetime)?;
+            println!("Version: {}", version);
+            match version.layout_warning(&settings.cli_token_key) {
+                Some(warning) => println!("Warning: {}", warning),
+                None => println!(
+                    "cli_token_key '{}' matches this version's {} layout.",
+                    settings.cli_token_key, settings.cli_config_filename
+                ),
+            }
+        }
+        Commands::Binary(BinaryCommands::Bind(args)) => {
+            let mut profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get_mut(&args.profile_name).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    args.profile_name, settings.profiles_filename
+                )
+            })?;
+            profile.spacetime_command = args.command.clone();
+            let spacetime = spacetime_binary(&settings, Some(profile))?;
+            write_profiles(&settings, &profiles)?;
+            match args.command {
+                Some(_) => println!(
+                    "Profile '{}' now uses {}.",
+                    args.profile_name,
+                    spacetime.display()
+                ),
+                None => println!(
+                    "Profile '{}' now uses the global spacetime_command.",
+                    args.profile_name
+                ),
+            }
+            if let Ok(version) = cli_version::CliVersion::detect(&spacetime) {
+                if let Some(warning) = version.layout_warning(&settings.cli_token_key) {
+                    println!("Warning: {}", warning);
+                }
+            }
+        }
+        Commands::Discover => {
+            let configured = get_cli_toml_path(&settings)?;
+            let mut candidates = discovery::cli_config_candidates(&settings.cli_config_filename);
+            if !candidates
+                .iter()
+                .any(|candidate| candidate.path == configured)
+            {
+                candidates.insert(
+                    0,
+                    discovery::Candidate {
+                        path: configured.clone(),
+                        description: "cli_config_dir_from_home",
+                    },
+                );
+            }
+            println!("SpacetimeDB CLI config locations ('*' is the one in use):");
+            for candidate in &candidates {
+                let mut notes = Vec::new();
+                if candidate.exists() {
+                    notes.push("exists");
+                    if candidate.is_legacy() {
+                        notes.push("legacy layout, see `migrate`");
                     }
-                    println!("Active token: {}", mask_token(active_token_str));
                 } else {
+                    notes.push("missing");
+                }
+                println!(
+                    "{} {} ({}) [{}]",
+                    if candidate.path == configured {
+                        "*"
+                    } else {
+                        " "
+                    },
+                    candidate.path.display(),
+                    candidate.description,
+                    notes.join(", ")
+                );
+            }
+        }
+        Commands::Migrate(args) => {
+            let source = match args.from {
+                Some(path) => path,
+                None => discovery::cli_config_candidates(&settings.cli_config_filename)
+                    .into_iter()
+                    .find(|candidate| candidate.exists() && candidate.is_legacy())
+                    .map(|candidate| candidate.path)
+                    .context(
+                        "No legacy SpacetimeDB config with identity_configs found. Pass --from <PATH>.",
+                    )?,
+            };
+            let content = fs::read_to_string(&source)
+                .with_context(|| format!("Failed to read {:?}", source))?;
+            let legacy = content
+                .parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse {:?}", source))?;
+            let default_identity = legacy
+                .get("default_identity")
+                .and_then(|item| item.as_str());
+            let identity_configs = legacy
+                .get("identity_configs")
+                .and_then(|item| item.as_array_of_tables())
+                .with_context(|| format!("{:?} has no [[identity_configs]].", source))?;
+
+            println!("Importing tokens from {:?}...", source);
+            let mut profiles = read_profiles(&settings)?;
+            let mut imported = 0;
+            let mut default_token = None;
+            for entry in identity_configs.iter() {
+                let Some(token) = entry.get("token").and_then(|item| item.as_str()) else {
+                    continue;
+                };
+                let identity = entry.get("identity").and_then(|item| item.as_str());
+                let nickname = entry.get("nickname").and_then(|item| item.as_str());
+                if default_identity
+                    .is_some_and(|default| Some(default) == identity || Some(default) == nickname)
+                {
+                    default_token = Some(token.to_string());
+                }
+                if let Some(existing) = find_profile_by_token(&profiles, token) {
                     println!(
-                        "Active token key '{}' in {} is not a string.",
-                        settings.cli_token_key, settings.cli_config_filename
+                        "  Skipping {}: already stored as '{}'.",
+                        identity.or(nickname).unwrap_or("identity"),
+                        existing
                     );
+                    continue;
+                }
+                let base_name = match (nickname, identity) {
+                    (Some(nickname), _) => nickname.to_string(),
+                    (None, Some(identity)) => {
+                        format!("legacy-{}", identity.chars().take(8).collect::<String>())
+                    }
+                    (None, None) => "legacy".to_string(),
+                };
+                let mut profile_name = base_name.clone();
+                let mut suffix = 2;
+                while profiles.0.contains_key(&profile_name) {
+                    profile_name = format!("{}-{}", base_name, suffix);
+                    suffix += 1;
                 }
-            } else {
                 println!(
-                    "No active token (key '{}') found in {}.",
-                    settings.cli_token_key, settings.cli_config_filename
+                    "  {} -> profile '{}'",
+                    identity.or(nickname).unwrap_or("identity"),
+                    profile_name
                 );
+                profiles
+                    .0
+                    .insert(profile_name, Profile::new(token.to_string()));
+                imported += 1;
             }
-        }
-        Commands::Delete(args) => {
-            let mut profiles = read_profiles(&settings)?; // Renamed
-            if profiles.0.remove(&args.profile_name).is_some() {
-                // Renamed
-                write_profiles(&settings, &profiles)?; // Renamed
+
+            let activate = default_token.filter(|_| read_active_token(&settings).is_err());
+            if args.dry_run {
+                if activate.is_some() {
+                    println!("The default identity's token would become the active token.");
+                }
+                println!("Dry run: {} profile(s) would be imported.", imported);
+                return Ok(());
+            }
+            if imported > 0 {
+                write_profiles(&settings, &profiles)?;
+            }
+            if let Some(token) = activate {
+                write_active_token(&settings, Some(&token))?;
+                println!("Set the default identity's token as the active token.");
+            }
+            println!("Imported {} profile(s).", imported);
+            if settings.cli_token_key.starts_with("identity_configs") {
                 println!(
-                    "Profile '{}' deleted from {}.", // Renamed
-                    args.profile_name,
-                    settings.profiles_filename // Renamed
+                    "Note: cli_token_key still points at the legacy layout. Set it to '{}' for SpacetimeDB CLI 0.12 and later.",
+                    cli_version::TOKEN_KEY
+                );
+            }
+        }
+        Commands::Config(_) => unreachable!("handled before the settings are loaded"),
+        Commands::Server(ServerCommands::Add(args)) => {
+            let (protocol, host) = match args.url.split_once("://") {
+                Some((protocol, host)) => {
+                    (protocol.to_string(), host.trim_end_matches('/').to_string())
+                }
+                None => anyhow::bail!(
+                    "Server URL '{}' must include a protocol, e.g. http://{}",
+                    args.url,
+                    args.url
+                ),
+            };
+            let mut cli_toml = read_or_create_cli_toml(&settings)?;
+            if !cli_toml.contains_key("server_configs") {
+                cli_toml.insert("server_configs", Item::ArrayOfTables(ArrayOfTables::new()));
+            }
+            let server_configs = cli_toml["server_configs"]
+                .as_array_of_tables_mut()
+                .with_context(|| {
+                    format!(
+                        "'server_configs' in {} is not an array of tables.",
+                        settings.cli_config_filename
+                    )
+                })?;
+            if server_configs.iter().any(|config| {
+                config.get("nickname").and_then(|n| n.as_str()) == Some(&args.nickname)
+            }) {
+                anyhow::bail!(
+                    "Server '{}' already exists in {}. Remove it first.",
+                    args.nickname,
+                    settings.cli_config_filename
                 );
+            }
+            let mut config = Table::new();
+            config["nickname"] = toml_edit::value(args.nickname.clone());
+            config["host"] = toml_edit::value(host);
+            config["protocol"] = toml_edit::value(protocol);
+            server_configs.push(config);
+            if args.default {
+                cli_toml["default_server"] = toml_edit::value(args.nickname.clone());
+            }
+            write_cli_toml(&settings, &cli_toml)?;
+            println!(
+                "Added server '{}' ({}){}.",
+                args.nickname,
+                args.url,
+                if args.default { " as the default" } else { "" }
+            );
+        }
+        Commands::Server(ServerCommands::List) => {
+            let cli_toml_path = get_cli_toml_path(&settings)?;
+            let cli_toml = if cli_toml_path.exists() {
+                read_cli_toml(&settings)?
             } else {
+                DocumentMut::new()
+            };
+            let default_server = cli_toml
+                .get("default_server")
+                .and_then(|item| item.as_str())
+                .unwrap_or(DEFAULT_LOGIN_SERVER);
+            println!("Servers known to {}:", settings.cli_config_filename);
+            for (nickname, url) in known_servers(&cli_toml) {
+                let mut line = format!("- {}: {}", nickname, url);
+                let configured = cli_toml
+                    .get("server_configs")
+                    .and_then(|item| item.as_array_of_tables())
+                    .is_some_and(|configs| {
+                        configs.iter().any(|config| {
+                            config.get("nickname").and_then(|n| n.as_str()) == Some(&nickname)
+                        })
+                    });
+                if !configured {
+                    line.push_str(" (built-in)");
+                }
+                if nickname == default_server {
+                    line.push_str(" (default)");
+                }
+                println!("{}", line);
+            }
+        }
+        Commands::Server(ServerCommands::Remove(args)) => {
+            let mut cli_toml = read_cli_toml(&settings)?;
+            let removed = match cli_toml
+                .get_mut("server_configs")
+                .and_then(|item| item.as_array_of_tables_mut())
+            {
+                Some(server_configs) => {
+                    let before = server_configs.len();
+                    server_configs.retain(|config| {
+                        config.get("nickname").and_then(|n| n.as_str()) != Some(&args.nickname)
+                    });
+                    before != server_configs.len()
+                }
+                None => false,
+            };
+            if !removed {
+                anyhow::bail!(
+                    "Server '{}' not found in {}. Nothing to remove.",
+                    args.nickname,
+                    settings.cli_config_filename
+                );
+            }
+            if cli_toml
+                .get("default_server")
+                .and_then(|item| item.as_str())
+                == Some(&args.nickname)
+            {
+                cli_toml.remove("default_server");
                 println!(
-                    "Profile '{}' not found in {}. Nothing to delete.", // Renamed
-                    args.profile_name,
-                    settings.profiles_filename // Renamed
+                    "Warning: '{}' was the default server. default_server has been unset.",
+                    args.nickname
                 );
-                anyhow::bail!("Profile not found for deletion."); // Renamed
+            }
+            write_cli_toml(&settings, &cli_toml)?;
+
+            let profiles = read_profiles(&settings)?;
+            let mut bound: Vec<&String> = profiles
+                .0
+                .iter()
+                .filter(|(_, profile)| profile.server.as_ref() == Some(&args.nickname))
+                .map(|(name, _)| name)
+                .collect();
+            bound.sort();
+            if !bound.is_empty() {
+                println!(
+                    "Warning: These profiles are still bound to '{}': {:?}",
+                    args.nickname, bound
+                );
+            }
+            println!("Removed server '{}'.", args.nickname);
+        }
+        Commands::Server(ServerCommands::Default(args)) => {
+            let mut cli_toml = read_or_create_cli_toml(&settings)?;
+            if !known_servers(&cli_toml)
+                .iter()
+                .any(|(nickname, _)| *nickname == args.nickname)
+            {
+                anyhow::bail!(
+                    "Unknown server '{}'. Add it first with 'server add'.",
+                    args.nickname
+                );
+            }
+            cli_toml["default_server"] = toml_edit::value(args.nickname.clone());
+            write_cli_toml(&settings, &cli_toml)?;
+            println!("Default server set to '{}'.", args.nickname);
+        }
+        Commands::Server(ServerCommands::Bind(args)) => {
+            if let Some(nickname) = &args.nickname {
+                resolve_server_url(&settings, Some(nickname))?;
+            }
+            let mut profiles = read_profiles(&settings)?;
+            let profile = profiles.0.get_mut(&args.profile_name).with_context(|| {
+                format!(
+                    "Profile '{}' not found in {}.",
+                    args.profile_name, settings.profiles_filename
+                )
+            })?;
+            profile.server = args.nickname.clone();
+            write_profiles(&settings, &profiles)?;
+            match args.nickname {
+                Some(nickname) => println!(
+                    "Profile '{}' is now bound to server '{}'.",
+                    args.profile_name, nickname
+                ),
+                None => println!("Removed the server binding of '{}'.", args.profile_name),
             }
         }
         Commands::Setup => {
-            let mut current_settings = load_app_settings().unwrap_or_else(|e| {
+            let current_settings = load_app_settings().unwrap_or_else(|e| {
                 println!(
                     "Warning: Could not load existing settings ({}). Using defaults.",
                     e
                 );
                 AppSettings::default()
             });
+            run_setup_wizard(current_settings)?;
+        }
+    }
 
-            println!("Current configuration (leave blank to keep current value):");
+    Ok(())
+}
 
-            let mut input = String::new();
-            println!(
-                "Profiles filename [{}]: ",         // Renamed
-                current_settings.profiles_filename  // Renamed
-            );
-            std::io::stdin().read_line(&mut input)?;
-            if !input.trim().is_empty() {
-                current_settings.profiles_filename = input.trim().to_string(); // Renamed
-            }
-            input.clear();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_server::{unreachable_url, StubServer};
 
-            println!(
-                "SpacetimeDB CLI config directory (from home) [{}]: ",
-                current_settings.cli_config_dir_from_home
-            );
-            std::io::stdin().read_line(&mut input)?;
-            if !input.trim().is_empty() {
-                current_settings.cli_config_dir_from_home = input.trim().to_string();
-            }
-            input.clear();
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stt-main-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
 
-            println!(
-                "SpacetimeDB CLI config filename [{}]: ",
-                current_settings.cli_config_filename
-            );
-            std::io::stdin().read_line(&mut input)?;
-            if !input.trim().is_empty() {
-                current_settings.cli_config_filename = input.trim().to_string();
+    fn settings_in(dir: &Path) -> AppSettings {
+        AppSettings {
+            cli_config_dir_from_home: dir.to_string_lossy().into_owned(),
+            ..AppSettings::default()
+        }
+    }
+
+    fn token_issued_by(issuer: &str) -> String {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        let claims = serde_json::json!({ "iss": issuer, "sub": "alice" });
+        format!("e30.{}.sig", URL_SAFE_NO_PAD.encode(claims.to_string()))
+    }
+
+    fn exec_args(database: &str, args: &[&str]) -> Vec<String> {
+        let mut args = args.iter().map(|arg| arg.to_string()).collect();
+        insert_context_database(&mut args, database.to_string());
+        args
+    }
+
+    /// The field names of `AppSettings`, as its serde derive declares them.
+    fn app_settings_fields() -> &'static [&'static str] {
+        use serde::de::{self, Visitor};
+
+        struct FieldNames(Option<&'static [&'static str]>);
+
+        impl<'de> de::Deserializer<'de> for &mut FieldNames {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(
+                self,
+                _visitor: V,
+            ) -> std::result::Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
             }
-            input.clear();
 
-            println!(
-                "SpacetimeDB CLI token key [{}]: ",
-                current_settings.cli_token_key
-            );
-            std::io::stdin().read_line(&mut input)?;
-            if !input.trim().is_empty() {
-                current_settings.cli_token_key = input.trim().to_string();
+            fn deserialize_struct<V: Visitor<'de>>(
+                self,
+                _name: &'static str,
+                fields: &'static [&'static str],
+                _visitor: V,
+            ) -> std::result::Result<V::Value, Self::Error> {
+                self.0 = Some(fields);
+                Err(de::Error::custom("only the field names are needed"))
             }
 
-            write_app_settings(&current_settings)?;
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+                byte_buf option unit unit_struct newtype_struct seq tuple
+                tuple_struct map enum identifier ignored_any
+            }
         }
+
+        let mut names = FieldNames(None);
+        let _ = AppSettings::deserialize(&mut names);
+        names.0.expect("AppSettings is a struct")
     }
 
-    Ok(())
+    #[test]
+    fn setting_keys_match_the_settings_struct() {
+        let mut keys: Vec<String> = SettingKey::value_variants()
+            .iter()
+            .map(|key| key.name())
+            .collect();
+        keys.sort();
+        let mut fields: Vec<String> = app_settings_fields()
+            .iter()
+            .map(|field| field.to_string())
+            .collect();
+        fields.sort();
+        assert_eq!(keys, fields);
+
+        // Every key but the lists left out while empty is written to config.toml
+        let mut written: Vec<String> = settings_to_table(&AppSettings::default())
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        written.push(SettingKey::Targets.name());
+        written.sort();
+        assert_eq!(keys, written);
+    }
+
+    #[test]
+    fn spacetime_subcommand_skips_global_options() {
+        assert_eq!(
+            spacetime_subcommand(&["--root-dir", "/srv/st", "server", "add"]).as_deref(),
+            Some("server")
+        );
+        assert_eq!(
+            spacetime_subcommand(&["--config-path=x.toml", "logs", "db"]).as_deref(),
+            Some("logs")
+        );
+        assert_eq!(spacetime_subcommand(&["--version"]), None);
+    }
+
+    #[test]
+    fn exec_inserts_the_context_database_only_when_none_is_given() {
+        assert_eq!(
+            exec_args("ctxdb", &["sql", "SELECT 1"]),
+            ["sql", "ctxdb", "SELECT 1"]
+        );
+        assert_eq!(
+            exec_args("ctxdb", &["sql", "mydb", "SELECT 1"]),
+            ["sql", "mydb", "SELECT 1"]
+        );
+        // Option values are not mistaken for a database
+        assert_eq!(
+            exec_args("ctxdb", &["logs", "-n", "50", "-f"]),
+            ["logs", "ctxdb", "-n", "50", "-f"]
+        );
+        assert_eq!(
+            exec_args("ctxdb", &["logs", "--server=local", "mydb"]),
+            ["logs", "--server=local", "mydb"]
+        );
+        assert_eq!(
+            exec_args("ctxdb", &["call", "-s", "local", "my_reducer"]),
+            ["call", "ctxdb", "-s", "local", "my_reducer"]
+        );
+        assert_eq!(exec_args("ctxdb", &["publish"]), ["publish", "ctxdb"]);
+        assert_eq!(
+            exec_args("ctxdb", &["publish", "-p", "server", "mydb"]),
+            ["publish", "-p", "server", "mydb"]
+        );
+        assert_eq!(exec_args("ctxdb", &["version"]), ["version"]);
+    }
+
+    #[test]
+    fn issuer_check_uses_the_default_server_not_the_bound_one() {
+        let dir = temp_dir("issuer");
+        let settings = settings_in(&dir);
+        fs::write(dir.join("cli.toml"), "default_server = \"maincloud\"\n").unwrap();
+        let local_token = token_issued_by("http://localhost:3000");
+
+        // `switch` on a profile bound to `local` still talks to maincloud
+        let error = check_issuer_matches(&settings, &local_token, None, false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("issued locally"), "{}", error);
+        assert!(check_issuer_matches(&settings, &local_token, None, true).is_ok());
+        // `context use local` makes local the default, so the token fits
+        assert!(check_issuer_matches(&settings, &local_token, Some("local"), false).is_ok());
+
+        fs::write(dir.join("cli.toml"), "default_server = \"local\"\n").unwrap();
+        assert!(check_issuer_matches(&settings, &local_token, None, false).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn schema_version_cannot_be_saved_as_a_profile_name() {
+        let mut profiles = UserProfiles::default();
+        profiles.0.insert(
+            schema::VERSION_KEY.to_string(),
+            Profile::new("t".to_string()),
+        );
+        let message = serialize_profiles(&profiles, "profiles.toml")
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("reserved in profiles.toml"), "{}", message);
+
+        profiles.0.clear();
+        profiles
+            .0
+            .insert("dev".to_string(), Profile::new("t".to_string()));
+        let content = serialize_profiles(&profiles, "profiles.toml").unwrap();
+        assert!(content.starts_with("schema_version = 1\n"), "{}", content);
+        let mut table: toml::Table = content.parse().unwrap();
+        table.remove(schema::VERSION_KEY);
+        let read: UserProfiles = table.try_into().unwrap();
+        assert_eq!(read.0["dev"], Profile::new("t".to_string()));
+    }
+
+    #[test]
+    fn check_online_records_last_verified_only_for_valid_tokens() {
+        let stub = StubServer::start(|request| {
+            let status = match request.authorization.as_deref() {
+                Some("Bearer good") => 204,
+                Some("Bearer other") => 400,
+                _ => 401,
+            };
+            (status, String::new())
+        });
+        let client = api::ApiClient::new(&stub.url());
+
+        let mut good = Profile::new("good".to_string());
+        let mut other = Profile::new("other".to_string());
+        let mut bad = Profile::new("bad".to_string());
+        bad.last_verified = Some(42);
+        assert_eq!(
+            verify_profile(&client, "c200", &mut good).unwrap(),
+            api::Verification::Valid
+        );
+        assert_eq!(
+            verify_profile(&client, "c200", &mut other).unwrap(),
+            api::Verification::IdentityMismatch
+        );
+        assert_eq!(
+            verify_profile(&client, "c200", &mut bad).unwrap(),
+            api::Verification::Rejected(401)
+        );
+        assert!(good.last_verified.is_some());
+        assert_eq!(other.last_verified, None);
+        // A rejection keeps the time of the last successful check
+        assert_eq!(bad.last_verified, Some(42));
+
+        let mut unreachable = Profile::new("good".to_string());
+        assert!(verify_profile(
+            &api::ApiClient::new(&unreachable_url()),
+            "c200",
+            &mut unreachable
+        )
+        .is_err());
+        assert_eq!(unreachable.last_verified, None);
+    }
+
+    #[test]
+    fn ws_token_prints_the_token_or_a_json_object() {
+        let stub = StubServer::start(|_| (200, r#"{"token": "short.lived"}"#.to_string()));
+        let profile = Profile::new("long.lived".to_string());
+        assert_eq!(
+            ws_token_output("alice", &profile, &stub.url(), false).unwrap(),
+            "short.lived"
+        );
+
+        let json = ws_token_output("alice", &profile, &stub.url(), true).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"profile":"alice","server":"{}","token":"short.lived"}}"#,
+                stub.url()
+            )
+        );
+
+        let requests = stub.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/v1/identity/websocket-token");
+        assert_eq!(
+            requests[0].authorization.as_deref(),
+            Some("Bearer long.lived")
+        );
+    }
+
+    #[test]
+    fn ws_token_reports_http_errors() {
+        let stub = StubServer::start(|_| (401, "unauthorized".to_string()));
+        let profile = Profile::new("long.lived".to_string());
+        let error = ws_token_output("alice", &profile, &stub.url(), true)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("HTTP 401"), "{}", error);
+        assert!(error.contains("unauthorized"), "{}", error);
+    }
 }
@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory holding per-project settings, e.g. `.stt/profile`
+pub const PROJECT_DIR_NAME: &str = ".stt";
+const PIN_FILENAME: &str = "profile";
+
+/// A profile pinned to a directory tree by a `.stt/profile` file.
+pub struct Pin {
+    pub path: PathBuf,
+    pub profile_name: String,
+}
+
+/// Finds the nearest pin in `start` or one of its parent directories.
+pub fn find(start: &Path) -> Option<Pin> {
+    start.ancestors().find_map(|dir| {
+        let path = dir.join(PROJECT_DIR_NAME).join(PIN_FILENAME);
+        let profile_name = fs::read_to_string(&path).ok()?.trim().to_string();
+        (!profile_name.is_empty()).then_some(Pin { path, profile_name })
+    })
+}
+
+/// Pins `profile_name` to `dir`, returning the path of the pin file.
+pub fn write(dir: &Path, profile_name: &str) -> Result<PathBuf> {
+    let project_dir = dir.join(PROJECT_DIR_NAME);
+    fs::create_dir_all(&project_dir)
+        .with_context(|| format!("Failed to create directory {:?}", project_dir))?;
+    let path = project_dir.join(PIN_FILENAME);
+    fs::write(&path, format!("{}\n", profile_name))
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(path)
+}
+
+/// Removes the pin in `dir` itself (not its parents). Returns whether there was one.
+pub fn remove(dir: &Path) -> Result<bool> {
+    let path = dir.join(PROJECT_DIR_NAME).join(PIN_FILENAME);
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+    Ok(true)
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+use toml_edit::DocumentMut;
+
+/// Top-level key holding the schema version of config.toml and profiles.toml.
+/// Files without it are version 0, i.e. written before versions existed.
+pub const VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a document from version `n` to `n + 1`, where `n` is its index
+/// in the list passed to [`upgrade`].
+pub type Migration = fn(&mut DocumentMut) -> Result<()>;
+
+/// Reads the schema version of a parsed file.
+pub fn version_of(table: &toml::Table, path: &Path) -> Result<u32> {
+    match table.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(toml::Value::Integer(version)) => u32::try_from(*version)
+            .with_context(|| format!("Invalid {} {} in {:?}", VERSION_KEY, version, path)),
+        // Not echoed: in older profiles files this may be a profile holding a token
+        Some(_) => anyhow::bail!(
+            "{:?} uses '{}' for something other than a schema version. Rename that entry and try again.",
+            path,
+            VERSION_KEY
+        ),
+    }
+}
+
+/// Fails if `table` was written by a newer release that knows more schema versions.
+pub fn ensure_supported(table: &toml::Table, path: &Path, current: u32) -> Result<()> {
+    let version = version_of(table, path)?;
+    if version > current {
+        anyhow::bail!(
+            "{:?} has schema version {}, but this spacetime-token only understands up to version {}. It was written by a newer spacetime-token; upgrade to use it.",
+            path,
+            version,
+            current
+        );
+    }
+    Ok(())
+}
+
+/// Brings the file at `path` up to version `migrations.len()`. The original
+/// is copied into `backups_dir` before anything is changed. Missing and empty
+/// files are left alone; they are stamped when first written.
+pub fn upgrade(path: &Path, migrations: &[Migration], backups_dir: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    if content.trim().is_empty() {
+        return Ok(());
+    }
+    let table = content
+        .parse::<toml::Table>()
+        .with_context(|| format!("Failed to parse {:?}", path))?;
+    let current = migrations.len() as u32;
+    ensure_supported(&table, path, current)?;
+    let version = version_of(&table, path)?;
+    if version == current {
+        return Ok(());
+    }
+
+    let filename = path
+        .file_name()
+        .with_context(|| format!("{:?} has no file name", path))?
+        .to_string_lossy();
+    fs::create_dir_all(backups_dir)
+        .with_context(|| format!("Failed to create directory {:?}", backups_dir))?;
+    let stem = format!("{}.v{}-{}", filename, version, crate::token::now_unix());
+    // Never overwrite an earlier backup taken in the same second
+    let backup_path = (0..)
+        .map(|n| match n {
+            0 => backups_dir.join(format!("{}.bak", stem)),
+            n => backups_dir.join(format!("{}-{}.bak", stem, n)),
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded range");
+    fs::copy(path, &backup_path)
+        .with_context(|| format!("Failed to back up {:?} to {:?}", path, backup_path))?;
+
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse {:?}", path))?;
+    for (from, migration) in migrations.iter().enumerate().skip(version as usize) {
+        migration(&mut doc).with_context(|| {
+            format!(
+                "Failed to upgrade {:?} from schema version {} to {}",
+                path,
+                from,
+                from + 1
+            )
+        })?;
+    }
+    doc.remove(VERSION_KEY);
+    fs::write(path, stamp(&doc.to_string(), current))
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    // stderr, so output meant for `eval` or scripts stays clean
+    eprintln!(
+        "Upgraded {:?} from schema version {} to {} (backup at {:?}).",
+        path, version, current, backup_path
+    );
+    Ok(())
+}
+
+/// Prepends the version key to freshly serialized TOML.
+pub fn stamp(content: &str, version: u32) -> String {
+    format!("{} = {}\n\n{}", VERSION_KEY, version, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stt-schema-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn add_marker(doc: &mut DocumentMut) -> Result<()> {
+        doc["migrated"] = toml_edit::value(true);
+        Ok(())
+    }
+
+    fn backups(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn upgrading_a_v0_file_backs_it_up_and_stamps_it() {
+        let dir = temp_dir("v0");
+        let path = dir.join("config.toml");
+        let backups_dir = dir.join("backups");
+        let original = "# keep me\ncli_token_key = \"auth.token\"\n";
+        fs::write(&path, original).unwrap();
+
+        upgrade(&path, &[add_marker], &backups_dir).unwrap();
+
+        let saved = backups(&backups_dir);
+        assert_eq!(saved.len(), 1);
+        let backup_name = saved[0].file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            backup_name.starts_with("config.toml.v0-"),
+            "{}",
+            backup_name
+        );
+        assert!(backup_name.ends_with(".bak"), "{}", backup_name);
+        assert_eq!(fs::read_to_string(&saved[0]).unwrap(), original);
+
+        let upgraded = fs::read_to_string(&path).unwrap();
+        assert!(upgraded.starts_with("schema_version = 1\n"), "{}", upgraded);
+        assert!(upgraded.contains("# keep me"));
+        let table: toml::Table = upgraded.parse().unwrap();
+        assert_eq!(table["cli_token_key"].as_str(), Some("auth.token"));
+        assert_eq!(table["migrated"].as_bool(), Some(true));
+
+        // Already current: nothing to do, no second backup
+        upgrade(&path, &[add_marker], &backups_dir).unwrap();
+        assert_eq!(backups(&backups_dir).len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn earlier_backups_are_never_overwritten() {
+        let dir = temp_dir("backups");
+        let path = dir.join("profiles.toml");
+        let backups_dir = dir.join("backups");
+        fs::write(&path, "first = \"a\"\n").unwrap();
+        upgrade(&path, &[add_marker], &backups_dir).unwrap();
+        fs::write(&path, "second = \"b\"\n").unwrap();
+        upgrade(&path, &[add_marker], &backups_dir).unwrap();
+
+        let contents: Vec<String> = backups(&backups_dir)
+            .iter()
+            .map(|backup| fs::read_to_string(backup).unwrap())
+            .collect();
+        assert_eq!(contents.len(), 2);
+        assert!(contents.contains(&"first = \"a\"\n".to_string()));
+        assert!(contents.contains(&"second = \"b\"\n".to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_and_empty_files_are_left_alone() {
+        let dir = temp_dir("empty");
+        let backups_dir = dir.join("backups");
+        upgrade(&dir.join("missing.toml"), &[add_marker], &backups_dir).unwrap();
+        let empty = dir.join("empty.toml");
+        fs::write(&empty, "\n").unwrap();
+        upgrade(&empty, &[add_marker], &backups_dir).unwrap();
+        assert_eq!(fs::read_to_string(&empty).unwrap(), "\n");
+        assert!(!dir.join("missing.toml").exists());
+        assert!(!backups_dir.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_from_a_newer_release_are_refused_untouched() {
+        let dir = temp_dir("newer");
+        let path = dir.join("config.toml");
+        let backups_dir = dir.join("backups");
+        let original = "schema_version = 7\ncli_token_key = \"auth.token\"\n";
+        fs::write(&path, original).unwrap();
+
+        let error = upgrade(&path, &[add_marker], &backups_dir).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("schema version 7"), "{}", message);
+        assert!(message.contains("up to version 1"), "{}", message);
+        assert!(message.contains("newer spacetime-token"), "{}", message);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!backups_dir.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn non_integer_versions_are_rejected_without_echoing_them() {
+        let path = Path::new("profiles.toml");
+        let table: toml::Table = "schema_version = \"secret-token\"".parse().unwrap();
+        let message = format!("{:#}", version_of(&table, path).unwrap_err());
+        assert!(
+            message.contains("other than a schema version"),
+            "{}",
+            message
+        );
+        assert!(!message.contains("secret-token"), "{}", message);
+
+        let table: toml::Table = "schema_version = -1".parse().unwrap();
+        assert!(version_of(&table, path).is_err());
+    }
+}
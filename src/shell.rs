@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use std::path::PathBuf;
+
+/// Marks the block `setup` adds to a shell startup file
+pub const INTEGRATION_MARKER: &str = "# >>> spacetime-token >>>";
+const INTEGRATION_END_MARKER: &str = "# <<< spacetime-token <<<";
+
+/// Shells whose syntax `env` can print
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    /// bash, zsh and other POSIX shells
+    #[default]
+    Sh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    /// Maps a shell program such as `$SHELL` (`/bin/zsh`) to its syntax.
+    pub fn from_program(program: &str) -> Option<Self> {
+        match program.rsplit(['/', '\\']).next()? {
+            "bash" | "zsh" | "sh" | "dash" | "ksh" => Some(Shell::Sh),
+            "fish" => Some(Shell::Fish),
+            "pwsh" | "pwsh.exe" | "powershell" | "powershell.exe" => Some(Shell::Powershell),
+            _ => None,
+        }
+    }
+
+    /// A command that sets the environment variable `name` to `value`.
+    pub fn export(self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Sh => format!("export {}={}", name, quote_single(value)),
+            Shell::Fish => format!("set -gx {} {}", name, quote_single(value)),
+            Shell::Powershell => format!("$env:{} = {}", name, quote_powershell(value)),
+        }
+    }
+
+    /// A command that removes the environment variable `name`.
+    pub fn unset(self, name: &str) -> String {
+        match self {
+            Shell::Sh => format!("unset {}", name),
+            Shell::Fish => format!("set -e {}", name),
+            Shell::Powershell => format!("Remove-Item Env:{} -ErrorAction SilentlyContinue", name),
+        }
+    }
+
+    /// Startup-file lines defining `stt-use <profile>` (and `stt-use --unset`),
+    /// which run `env` for the current terminal.
+    pub fn integration(self, exe: &str) -> String {
+        let body = match self {
+            Shell::Sh => format!(
+                "stt-use() {{ eval \"$({} env \"$@\")\"; }}",
+                quote_single(exe)
+            ),
+            Shell::Fish => format!(
+                "function stt-use; {} env --shell fish $argv | source; end",
+                quote_single(exe)
+            ),
+            Shell::Powershell => format!(
+                "function stt-use {{ & {} env --shell powershell @args | Invoke-Expression }}",
+                quote_powershell(exe)
+            ),
+        };
+        format!(
+            "{}\n{}\n{}\n",
+            INTEGRATION_MARKER, body, INTEGRATION_END_MARKER
+        )
+    }
+
+    /// The startup file the integration goes into. `program` tells bash and zsh apart.
+    pub fn startup_file(self, program: &str) -> Option<PathBuf> {
+        let home_dir = dirs::home_dir()?;
+        match self {
+            Shell::Sh if program.ends_with("zsh") => Some(home_dir.join(".zshrc")),
+            Shell::Sh if program.ends_with("bash") => Some(home_dir.join(".bashrc")),
+            Shell::Sh => Some(home_dir.join(".profile")),
+            Shell::Fish => Some(
+                home_dir
+                    .join(".config")
+                    .join("fish")
+                    .join("conf.d")
+                    .join("spacetime-token.fish"),
+            ),
+            // The profile script location depends on the PowerShell edition
+            Shell::Powershell => None,
+        }
+    }
+}
+
+/// Single-quotes `value` for POSIX shells and fish.
+fn quote_single(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn quote_powershell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,106 @@
+use clap::ValueEnum;
+
+/// Client SDKs (and plain env files) a connection snippet can be printed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SnippetLang {
+    /// TypeScript SDK
+    Ts,
+    /// C# SDK
+    Csharp,
+    /// Rust SDK
+    Rust,
+    /// `KEY=value` lines for a .env file
+    Env,
+}
+
+pub const DEFAULT_TOKEN_ENV_VAR: &str = "SPACETIMEDB_TOKEN";
+
+/// Renders a ready-to-paste connection config for `lang`. With `token_env`,
+/// code snippets read the token from that environment variable instead of
+/// embedding it, and env snippets store it under that name.
+pub fn render(
+    lang: SnippetLang,
+    host: &str,
+    module: &str,
+    token: &str,
+    token_env: Option<&str>,
+) -> String {
+    match lang {
+        SnippetLang::Ts => {
+            let token = match token_env {
+                None => quote(token),
+                Some(name) => format!("process.env.{}", name),
+            };
+            format!(
+                "import {{ DbConnection }} from './module_bindings';\n\
+                 \n\
+                 const conn = DbConnection.builder()\n\
+                 \x20 .withUri({})\n\
+                 \x20 .withModuleName({})\n\
+                 \x20 .withToken({})\n\
+                 \x20 .build();",
+                quote(&websocket_uri(host)),
+                quote(module),
+                token
+            )
+        }
+        SnippetLang::Csharp => {
+            let token = match token_env {
+                None => quote(token),
+                Some(name) => {
+                    format!("Environment.GetEnvironmentVariable({})", quote(name))
+                }
+            };
+            format!(
+                "var conn = DbConnection.Builder()\n\
+                 \x20   .WithUri({})\n\
+                 \x20   .WithModuleName({})\n\
+                 \x20   .WithToken({})\n\
+                 \x20   .Build();",
+                quote(host),
+                quote(module),
+                token
+            )
+        }
+        SnippetLang::Rust => {
+            let token = match token_env {
+                None => format!("Some({})", quote(token)),
+                Some(name) => format!("std::env::var({}).ok()", quote(name)),
+            };
+            format!(
+                "let conn = DbConnection::builder()\n\
+                 \x20   .with_uri({})\n\
+                 \x20   .with_module_name({})\n\
+                 \x20   .with_token({})\n\
+                 \x20   .build()\n\
+                 \x20   .expect(\"Failed to connect\");",
+                quote(host),
+                quote(module),
+                token
+            )
+        }
+        SnippetLang::Env => format!(
+            "SPACETIMEDB_HOST={}\nSPACETIMEDB_MODULE={}\n{}={}",
+            host,
+            module,
+            token_env.unwrap_or(DEFAULT_TOKEN_ENV_VAR),
+            token
+        ),
+    }
+}
+
+/// The TypeScript SDK connects over websockets, so give it a `ws(s)://` URI.
+fn websocket_uri(host: &str) -> String {
+    if let Some(rest) = host.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = host.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        host.to_string()
+    }
+}
+
+/// A double-quoted string literal, valid in TypeScript, C# and Rust.
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| format!("\"{}\"", value))
+}
@@ -0,0 +1,87 @@
+//! A local HTTP server with canned responses, for testing the API client.
+
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+use tiny_http::{Header, Response, Server};
+
+/// A request as the stub received it
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub authorization: Option<String>,
+}
+
+/// Answers every request with `respond(request)`, a status and a JSON body.
+/// Stops when dropped.
+pub struct StubServer {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl StubServer {
+    pub fn start(respond: impl Fn(&Recorded) -> (u16, String) + Send + 'static) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("failed to start stub server"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let server = Arc::clone(&server);
+            let requests = Arc::clone(&requests);
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let recorded = Recorded {
+                        method: request.method().to_string(),
+                        path: request.url().to_string(),
+                        authorization: request
+                            .headers()
+                            .iter()
+                            .find(|header| header.field.equiv("Authorization"))
+                            .map(|header| header.value.to_string()),
+                    };
+                    let (status, body) = respond(&recorded);
+                    requests.lock().unwrap().push(recorded);
+                    let header =
+                        Header::from_bytes("Content-Type", "application/json").expect("header");
+                    let _ = request.respond(
+                        Response::from_string(body)
+                            .with_status_code(status)
+                            .with_header(header),
+                    );
+                }
+            })
+        };
+        Self {
+            server,
+            thread: Some(thread),
+            requests,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.server.server_addr())
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The URL of a port nothing listens on, for transport errors.
+pub fn unreachable_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+    let addr = listener.local_addr().expect("no local address");
+    drop(listener);
+    format!("http://{}", addr)
+}
@@ -0,0 +1,649 @@
+use crate::key_path::KeyPath;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{fs, ops::Range, path::PathBuf};
+use toml_edit::DocumentMut;
+
+/// An extra file that receives the active token whenever it changes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TokenTarget {
+    /// Path of the file: absolute, or starting with `~/` for the home directory
+    pub path: String,
+    pub format: TargetFormat,
+    /// Where the token goes: a dotted key path (toml/json/yaml) or a variable name (dotenv)
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetFormat {
+    Toml,
+    Json,
+    Yaml,
+    Dotenv,
+}
+
+impl TokenTarget {
+    /// The target's file. Relative paths are refused, since they would
+    /// depend on the directory a command happens to run in.
+    pub fn resolved_path(&self) -> Result<PathBuf> {
+        let path = match self.path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()
+                .context("Failed to get home directory")?
+                .join(rest),
+            None => PathBuf::from(&self.path),
+        };
+        if !path.is_absolute() {
+            anyhow::bail!(
+                "Target path '{}' is relative. Use an absolute path or one starting with '~/'.",
+                self.path
+            );
+        }
+        Ok(path)
+    }
+
+    /// Writes `token` into the target file, creating it if needed and
+    /// leaving the rest of its contents as they were.
+    pub fn write_token(&self, token: &str) -> Result<()> {
+        let path = self.resolved_path()?;
+        let content = if path.exists() {
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?
+        } else {
+            if let Some(parent_dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(parent_dir)
+                    .with_context(|| format!("Failed to create directory {:?}", parent_dir))?;
+            }
+            String::new()
+        };
+        let updated = match self.format {
+            TargetFormat::Toml => set_toml(&content, &self.key, token),
+            TargetFormat::Json => set_json(&content, &self.key, token),
+            TargetFormat::Yaml => set_yaml(&content, &self.key, token),
+            TargetFormat::Dotenv => Ok(set_dotenv(&content, &self.key, token)),
+        }
+        .with_context(|| format!("Failed to update '{}' in {:?}", self.key, path))?;
+        fs::write(&path, updated).with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+fn set_toml(content: &str, key: &str, token: &str) -> Result<String> {
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .context("File is not valid TOML")?;
+    KeyPath::parse(key)?.set(&mut doc, token)?;
+    Ok(doc.to_string())
+}
+
+/// Sets a string in a JSON object by splicing it into the text, so inline
+/// arrays, spacing and everything else outside the value stay as they were.
+fn set_json(content: &str, key: &str, token: &str) -> Result<String> {
+    let path = KeyPath::parse(key)?;
+    let keys = path.plain_keys()?;
+    let content = if content.trim().is_empty() {
+        "{}\n"
+    } else {
+        serde_json::from_str::<serde_json::Value>(content).context("File is not valid JSON")?;
+        content
+    };
+    let token = serde_json::to_string(token)?;
+    let indent_unit = content
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim_start();
+            (!trimmed.is_empty() && trimmed.len() < line.len())
+                .then(|| &line[..line.len() - trimmed.len()])
+        })
+        .next()
+        .unwrap_or("  ");
+
+    let mut start = skip_whitespace(content, 0);
+    // Separator between a key and its value, copied from the file
+    let mut colon = ": ";
+    for (depth, segment) in keys.iter().enumerate() {
+        let object = JsonObject::parse(content, start).with_context(|| match depth {
+            0 => "The file does not contain a JSON object".to_string(),
+            _ => format!("'{}' is not an object", keys[..depth].join(".")),
+        })?;
+        if let Some(last) = object.members.last() {
+            colon = &content[last.key.end..last.value.start];
+        }
+        // Duplicate keys: the last one wins, as when the file is parsed
+        match object
+            .members
+            .iter()
+            .rev()
+            .find(|member| member.name == *segment)
+        {
+            Some(member) if depth == keys.len() - 1 => {
+                return Ok(splice(content, member.value.clone(), &token));
+            }
+            Some(member) => start = member.value.start,
+            None => {
+                return Ok(object.insert(content, &keys[depth..], &token, indent_unit, colon));
+            }
+        }
+    }
+    anyhow::bail!("Key path is empty.")
+}
+
+fn splice(content: &str, range: Range<usize>, replacement: &str) -> String {
+    format!(
+        "{}{}{}",
+        &content[..range.start],
+        replacement,
+        &content[range.end..]
+    )
+}
+
+/// Byte ranges of a JSON object in a document already known to be valid
+struct JsonObject {
+    open: usize,
+    close: usize,
+    members: Vec<JsonMember>,
+}
+
+struct JsonMember {
+    name: String,
+    key: Range<usize>,
+    value: Range<usize>,
+}
+
+impl JsonObject {
+    /// Reads the object starting at `open`, or `None` if the value there is not an object.
+    fn parse(content: &str, open: usize) -> Option<Self> {
+        if content.as_bytes().get(open) != Some(&b'{') {
+            return None;
+        }
+        let mut members = Vec::new();
+        let mut position = skip_whitespace(content, open + 1);
+        while content.as_bytes().get(position)? == &b'"' {
+            let key = position..value_end(content, position);
+            let name = serde_json::from_str(&content[key.clone()]).ok()?;
+            // Past the ':'
+            let value_start = skip_whitespace(content, skip_whitespace(content, key.end) + 1);
+            let value = value_start..value_end(content, value_start);
+            position = skip_whitespace(content, value.end);
+            if content.as_bytes().get(position) == Some(&b',') {
+                position = skip_whitespace(content, position + 1);
+            }
+            members.push(JsonMember { name, key, value });
+        }
+        Some(Self {
+            open,
+            close: position,
+            members,
+        })
+    }
+
+    /// Adds `keys` (nested objects ending in `token`) as the last member,
+    /// formatted like the members already there.
+    fn insert(
+        &self,
+        content: &str,
+        keys: &[&str],
+        token: &str,
+        indent_unit: &str,
+        colon: &str,
+    ) -> String {
+        let Some(last) = self.members.last() else {
+            let line_start = content[..self.open].rfind('\n').map_or(0, |i| i + 1);
+            let line = &content[line_start..];
+            let line_indent = &line[..line.len() - line.trim_start().len()];
+            let member_indent = format!("{}{}", line_indent, indent_unit);
+            let member = render_member(keys, token, Some(&member_indent), indent_unit, colon);
+            let inside = format!("\n{}{}\n{}", member_indent, member, line_indent);
+            return splice(content, self.open + 1..self.close, &inside);
+        };
+        // The whitespace before the last key, after its ',' or '{'
+        let before_key = content[..last.key.start].trim_end().len();
+        let separator = match &content[before_key..last.key.start] {
+            "" if colon.ends_with(' ') => " ",
+            separator => separator,
+        };
+        let member_indent = separator.rfind('\n').map(|i| &separator[i + 1..]);
+        let member = render_member(keys, token, member_indent, indent_unit, colon);
+        splice(
+            content,
+            last.value.end..last.value.end,
+            &format!(",{}{}", separator, member),
+        )
+    }
+}
+
+/// `"key": value`, with `keys[1..]` as nested objects. They span several
+/// lines when the member starts on its own line at `member_indent`.
+fn render_member(
+    keys: &[&str],
+    token: &str,
+    member_indent: Option<&str>,
+    indent_unit: &str,
+    colon: &str,
+) -> String {
+    let name = serde_json::Value::from(keys[0]).to_string();
+    if keys.len() == 1 {
+        return format!("{}{}{}", name, colon, token);
+    }
+    match member_indent {
+        Some(member_indent) => {
+            let inner_indent = format!("{}{}", member_indent, indent_unit);
+            let inner = render_member(&keys[1..], token, Some(&inner_indent), indent_unit, colon);
+            format!(
+                "{}{}{{\n{}{}\n{}}}",
+                name, colon, inner_indent, inner, member_indent
+            )
+        }
+        None => {
+            let inner = render_member(&keys[1..], token, None, indent_unit, colon);
+            format!("{}{}{{{}}}", name, colon, inner)
+        }
+    }
+}
+
+fn skip_whitespace(content: &str, position: usize) -> usize {
+    content[position..]
+        .find(|c: char| !c.is_ascii_whitespace())
+        .map_or(content.len(), |offset| position + offset)
+}
+
+/// Where the JSON value starting at `start` ends.
+fn value_end(content: &str, start: usize) -> usize {
+    let bytes = content.as_bytes();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (position, &byte) in bytes.iter().enumerate().skip(start) {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return position + 1;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return position + 1;
+                }
+            }
+            // The end of a number, `true`, `false` or `null`
+            b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n' if depth == 0 => {
+                return position;
+            }
+            _ => {}
+        }
+    }
+    bytes.len()
+}
+
+/// Sets a scalar in a block-style YAML mapping by editing lines in place, so
+/// comments and formatting elsewhere in the file survive.
+fn set_yaml(content: &str, key: &str, token: &str) -> Result<String> {
+    let path = KeyPath::parse(key)?;
+    let segments = path.plain_keys()?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    // (indent, key) of the mappings enclosing the current line
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    // Deepest matched ancestor: (number of matched segments, line index, indent)
+    let mut deepest: Option<(usize, usize, usize)> = None;
+    let mut child_indent = 2;
+
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('-') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        let Some((line_key, _)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let line_key = line_key.trim().trim_matches(|c| c == '"' || c == '\'');
+        while stack.last().is_some_and(|(parent, _)| *parent >= indent) {
+            stack.pop();
+        }
+        if let Some((parent_indent, _)) = stack.last() {
+            child_indent = indent - parent_indent;
+        }
+        stack.push((indent, line_key.to_string()));
+
+        let depth = stack.len();
+        let matches = depth <= segments.len()
+            && stack
+                .iter()
+                .zip(&segments)
+                .all(|((_, stacked), segment)| stacked == segment);
+        if !matches {
+            continue;
+        }
+        if depth == segments.len() {
+            let value = trimmed
+                .split_once(':')
+                .map_or("", |(_, value)| value.trim_start());
+            if value.starts_with('{') || has_children(&lines[index + 1..], indent) {
+                anyhow::bail!(
+                    "'{}' is a mapping or list; refusing to replace it with the token",
+                    key
+                );
+            }
+            let prefix = &line[..indent];
+            let comment = trimmed
+                .find(" #")
+                .map(|position| &trimmed[position..])
+                .unwrap_or_default();
+            lines[index] = format!(
+                "{}{}: \"{}\"{}",
+                prefix,
+                segments[depth - 1],
+                token,
+                comment
+            );
+            return Ok(join_lines(&lines, content));
+        }
+        if deepest.is_none_or(|(matched, _, _)| depth > matched) {
+            deepest = Some((depth, index, indent));
+        }
+    }
+
+    // Insert the missing keys below the deepest existing ancestor
+    let (matched, insert_at, base_indent) = match deepest {
+        Some((matched, index, indent)) => {
+            // Skip past the ancestor's existing children
+            let end = lines[index + 1..]
+                .iter()
+                .position(|line| {
+                    let trimmed = line.trim_start();
+                    !trimmed.is_empty() && line.len() - trimmed.len() <= indent
+                })
+                .map_or(lines.len(), |offset| index + 1 + offset);
+            (matched, end, indent + child_indent)
+        }
+        None => (0, lines.len(), 0),
+    };
+    let mut new_lines = Vec::new();
+    for (offset, segment) in segments[matched..].iter().enumerate() {
+        let indent = " ".repeat(base_indent + offset * child_indent);
+        if matched + offset == segments.len() - 1 {
+            new_lines.push(format!("{}{}: \"{}\"", indent, segment, token));
+        } else {
+            new_lines.push(format!("{}{}:", indent, segment));
+        }
+    }
+    lines.splice(insert_at..insert_at, new_lines);
+    Ok(join_lines(&lines, content))
+}
+
+/// Whether the lines following a key at `indent` belong to it: nested keys,
+/// list items or the lines of a block scalar.
+fn has_children(following: &[String], indent: usize) -> bool {
+    following
+        .iter()
+        .map(|line| (line.len() - line.trim_start().len(), line.trim_start()))
+        .find(|(_, trimmed)| !trimmed.is_empty() && !trimmed.starts_with('#'))
+        .is_some_and(|(child_indent, trimmed)| {
+            child_indent > indent || (child_indent == indent && trimmed.starts_with("- "))
+        })
+}
+
+/// Sets `KEY=value` in a dotenv file, keeping an existing `export` prefix and quote style.
+fn set_dotenv(content: &str, key: &str, token: &str) -> String {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut found = false;
+    for line in lines.iter_mut() {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        let (export, rest) = match trimmed.strip_prefix("export ") {
+            Some(rest) => ("export ", rest.trim_start()),
+            None => ("", trimmed),
+        };
+        let Some((name, value)) = rest.split_once('=') else {
+            continue;
+        };
+        if name.trim() != key {
+            continue;
+        }
+        let value = value.trim();
+        let (quote, comment) = match value.chars().next() {
+            Some(c @ ('"' | '\'')) => (
+                c.to_string(),
+                value[1..].find(c).map_or("", |end| &value[end + 2..]),
+            ),
+            _ => (
+                String::new(),
+                value.find(" #").map_or("", |start| &value[start..]),
+            ),
+        };
+        *line = format!(
+            "{}{}{}={}{}{}{}",
+            indent, export, key, quote, token, quote, comment
+        );
+        found = true;
+    }
+    if !found {
+        lines.push(format!("{}={}", key, token));
+    }
+    join_lines(&lines, content)
+}
+
+fn join_lines(lines: &[String], original: &str) -> String {
+    let mut output = lines.join("\n");
+    if original.is_empty() || original.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (case, file contents, key, expected contents)
+    type Case = (&'static str, &'static str, &'static str, &'static str);
+
+    #[test]
+    fn toml_targets() {
+        let cases: &[Case] = &[
+            ("empty file", "", "token", "token = \"tok\"\n"),
+            (
+                "existing key keeps its comments",
+                "# app\ntoken = \"old\" # keep\nother = 1\n",
+                "token",
+                "# app\ntoken = \"tok\" # keep\nother = 1\n",
+            ),
+            (
+                "missing nested key",
+                "name = \"game\"\n",
+                "client.auth.token",
+                "name = \"game\"\n\n[client]\n\n[client.auth]\ntoken = \"tok\"\n",
+            ),
+        ];
+        for (case, content, key, expected) in cases {
+            assert_eq!(
+                set_toml(content, key, "tok").unwrap(),
+                *expected,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn json_targets() {
+        let cases: &[Case] = &[
+            ("empty file", "", "token", "{\n  \"token\": \"tok\"\n}\n"),
+            (
+                "existing key keeps order and indentation",
+                "{\n    \"a\": 1,\n    \"token\": \"old\",\n    \"z\": 2\n}\n",
+                "token",
+                "{\n    \"a\": 1,\n    \"token\": \"tok\",\n    \"z\": 2\n}\n",
+            ),
+            (
+                "missing nested key",
+                "{\n  \"net\": {}\n}",
+                "net.spacetime.token",
+                "{\n  \"net\": {\n    \"spacetime\": {\n      \"token\": \"tok\"\n    }\n  }\n}",
+            ),
+            (
+                "inline arrays and objects are left alone",
+                "{\"ids\": [1,2], \"net\": {\"token\": \"old\", \"port\": 3000}}",
+                "net.token",
+                "{\"ids\": [1,2], \"net\": {\"token\": \"tok\", \"port\": 3000}}",
+            ),
+            (
+                "missing key appended after the last member",
+                "{\n    \"ids\": [1, 2],\n    \"name\": \"a,b}\"\n}\n",
+                "token",
+                "{\n    \"ids\": [1, 2],\n    \"name\": \"a,b}\",\n    \"token\": \"tok\"\n}\n",
+            ),
+            (
+                "missing nested key in an inline object",
+                "{\"net\": {\"port\": 3000}}",
+                "net.auth.token",
+                "{\"net\": {\"port\": 3000, \"auth\": {\"token\": \"tok\"}}}",
+            ),
+            (
+                "escaped keys and duplicates",
+                "{\"to\\\"k\": 1, \"token\": \"a\", \"token\": \"b\"}",
+                "token",
+                "{\"to\\\"k\": 1, \"token\": \"a\", \"token\": \"tok\"}",
+            ),
+        ];
+        for (case, content, key, expected) in cases {
+            assert_eq!(
+                set_json(content, key, "tok").unwrap(),
+                *expected,
+                "{}",
+                case
+            );
+        }
+
+        let error = set_json("{\"net\": 5}", "net.token", "tok").unwrap_err();
+        assert!(
+            error.to_string().contains("'net' is not an object"),
+            "{}",
+            error
+        );
+        assert!(set_json("// not json", "token", "tok").is_err());
+        assert!(set_json("[1]", "token", "tok").is_err());
+    }
+
+    #[test]
+    fn array_selectors_are_only_for_toml() {
+        assert!(set_toml("", "configs[0].token", "tok").is_ok());
+        for error in [
+            set_json("{}", "configs[0].token", "tok").unwrap_err(),
+            set_yaml("", "configs[0].token", "tok").unwrap_err(),
+        ] {
+            assert!(error.to_string().contains("only TOML"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn relative_target_paths_are_refused() {
+        let target = |path: &str| TokenTarget {
+            path: path.to_string(),
+            format: TargetFormat::Dotenv,
+            key: "TOKEN".to_string(),
+        };
+        let error = target(".env").write_token("tok").unwrap_err();
+        assert!(error.to_string().contains("is relative"), "{}", error);
+        assert!(target("~/.env").resolved_path().unwrap().is_absolute());
+        assert!(target("/srv/game/.env").resolved_path().is_ok());
+    }
+
+    #[test]
+    fn yaml_targets() {
+        let cases: &[Case] = &[
+            ("empty file", "", "bot.token", "bot:\n  token: \"tok\"\n"),
+            (
+                "existing key keeps comments",
+                "# bot config\nbot:\n  token: old # rotate me\n  name: x\n",
+                "bot.token",
+                "# bot config\nbot:\n  token: \"tok\" # rotate me\n  name: x\n",
+            ),
+            (
+                "quoted keys",
+                "'bot':\n    \"token\": 'old'\n",
+                "bot.token",
+                "'bot':\n    token: \"tok\"\n",
+            ),
+            (
+                "missing nested key below existing children",
+                "bot:\n    name: x\nother: 1\n",
+                "bot.auth.token",
+                "bot:\n    name: x\n    auth:\n        token: \"tok\"\nother: 1\n",
+            ),
+            (
+                "no trailing newline",
+                "bot:\n  token: old",
+                "bot.token",
+                "bot:\n  token: \"tok\"",
+            ),
+        ];
+        for (case, content, key, expected) in cases {
+            assert_eq!(
+                set_yaml(content, key, "tok").unwrap(),
+                *expected,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn yaml_refuses_to_replace_collections() {
+        for content in [
+            "bot:\n  auth:\n    token: old\n",
+            "bot:\n  auth:\n  # comment\n\n    token: old\n",
+            "bot:\n  auth:\n  - a\n",
+            "bot:\n  auth: |\n    text\n",
+            "bot:\n  auth: { token: old }\n",
+        ] {
+            let error = set_yaml(content, "bot.auth", "tok").unwrap_err();
+            assert!(error.to_string().contains("mapping or list"), "{}", content);
+        }
+    }
+
+    #[test]
+    fn dotenv_targets() {
+        let cases: &[Case] = &[
+            ("empty file", "", "TOKEN", "TOKEN=tok\n"),
+            (
+                "missing key is appended",
+                "# env\nOTHER=1\n",
+                "TOKEN",
+                "# env\nOTHER=1\nTOKEN=tok\n",
+            ),
+            (
+                "export prefix, quotes and comments",
+                "export TOKEN=\"old\" # from stt\nTOKEN_B='old'\n",
+                "TOKEN",
+                "export TOKEN=\"tok\" # from stt\nTOKEN_B='old'\n",
+            ),
+            (
+                "single quotes",
+                "TOKEN='old' # c\n",
+                "TOKEN",
+                "TOKEN='tok' # c\n",
+            ),
+            (
+                "unquoted with comment",
+                "  TOKEN = old # c\nA=1",
+                "TOKEN",
+                "  TOKEN=tok # c\nA=1",
+            ),
+        ];
+        for (case, content, key, expected) in cases {
+            assert_eq!(set_dotenv(content, key, "tok"), *expected, "{}", case);
+        }
+    }
+}
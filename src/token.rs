@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The claims of a SpacetimeDB token that stt cares about.
+/// Tokens are decoded without verifying their signature.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenClaims {
+    pub hex_identity: Option<String>,
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    pub exp: Option<u64>,
+}
+
+impl TokenClaims {
+    /// The identity the token maps to, as a lowercase hex string.
+    /// Falls back to deriving it from `iss`/`sub` when `hex_identity` is absent.
+    pub fn identity(&self) -> Option<String> {
+        match (&self.hex_identity, &self.iss, &self.sub) {
+            (Some(id), _, _) => Some(id.to_lowercase()),
+            (None, Some(iss), Some(sub)) => Some(identity_from_claims(iss, sub)),
+            _ => None,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.exp {
+            Some(exp) => exp <= now_unix(),
+            None => false,
+        }
+    }
+
+    /// A short human-readable description of when the token expires.
+    pub fn describe_expiry(&self) -> String {
+        let now = now_unix();
+        match self.exp {
+            None => "no expiry".to_string(),
+            Some(exp) if exp <= now => format!("expired {} ago", format_duration(now - exp)),
+            Some(exp) => format!("expires in {}", format_duration(exp - now)),
+        }
+    }
+}
+
+/// Derives a SpacetimeDB identity from a token's issuer and subject, the same
+/// way the server does: a `c200` prefix, a 4-byte checksum and 26 bytes of
+/// the BLAKE3 hash of `"<iss>|<sub>"`, formatted as big-endian hex.
+pub fn identity_from_claims(issuer: &str, subject: &str) -> String {
+    let input = format!("{}|{}", issuer, subject);
+    let first_hash = blake3::hash(input.as_bytes());
+    let id_hash = &first_hash.as_bytes()[..26];
+
+    let mut checksum_input = [0u8; 28];
+    checksum_input[0] = 0xc2;
+    checksum_input[1] = 0x00;
+    checksum_input[2..].copy_from_slice(id_hash);
+    let checksum_hash = blake3::hash(&checksum_input);
+
+    let mut identity = [0u8; 32];
+    identity[0] = 0xc2;
+    identity[1] = 0x00;
+    identity[2..6].copy_from_slice(&checksum_hash.as_bytes()[..4]);
+    identity[6..].copy_from_slice(id_hash);
+    crate::keys::to_hex(&identity)
+}
+
+/// Parses a duration such as `90`, `90s`, `15m`, `12h` or `7d` into seconds.
+pub fn parse_duration(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => input.split_at(index),
+        None => (input, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{}'.", input))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => anyhow::bail!("Invalid duration unit in '{}'. Use s, m, h or d.", input),
+    };
+    number
+        .checked_mul(multiplier)
+        .with_context(|| format!("Invalid duration '{}': too long.", input))
+}
+
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+pub fn decode_claims(token: &str) -> Result<TokenClaims> {
+    let mut parts = token.split('.');
+    let payload = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(payload), Some(_), None) => payload,
+        _ => anyhow::bail!("Token is not a JWT (expected three '.'-separated parts)."),
+    };
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .context("Failed to base64-decode the token payload.")?;
+    serde_json::from_slice(&payload).context("Failed to parse the token payload as JSON claims.")
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_with_and_without_units() {
+        for (input, seconds) in [
+            ("90", 90),
+            (" 90s ", 90),
+            ("15m", 900),
+            ("12h", 43_200),
+            ("7d", 604_800),
+            ("0", 0),
+        ] {
+            assert_eq!(parse_duration(input).unwrap(), seconds, "{}", input);
+        }
+    }
+
+    #[test]
+    fn invalid_durations_are_errors() {
+        for (input, message) in [
+            ("", "Invalid duration"),
+            ("h", "Invalid duration"),
+            ("-5m", "Invalid duration"),
+            ("5w", "Use s, m, h or d"),
+            ("5 m", "Use s, m, h or d"),
+            ("99999999999999999999", "Invalid duration"),
+            ("300000000000000000d", "too long"),
+        ] {
+            let error = parse_duration(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{:?}: {}", input, error);
+        }
+    }
+}